@@ -0,0 +1,451 @@
+use std::io::{Read, Write};
+
+use flate2::read::{GzDecoder, GzEncoder, ZlibDecoder, ZlibEncoder};
+use flate2::write;
+
+use crate::middleware::{Middleware, Next};
+use crate::parser::ParseError;
+use crate::request::{HttpRequest, HttpResponse, HttpStatusCode, HttpHeaders, HttpBody};
+
+/// A content coding the server can compress a response body with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentCoding {
+    Gzip,
+    /// The zlib format, which is what `deflate` means in HTTP.
+    Deflate,
+}
+
+impl ContentCoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentCoding::Gzip => "gzip",
+            ContentCoding::Deflate => "deflate",
+        }
+    }
+
+    /// Chooses the coding in codings the client prefers, going by the q-values of its
+    /// `Accept-Encoding`, with ties going to the earliest in codings. Returns `None` when the
+    /// body should be sent as it is: there is no `Accept-Encoding`, none of codings are
+    /// acceptable, or `identity` is listed, by name or by `*`, and preferred to all of them.
+    pub fn negotiate(accept_encoding: Option<&str>, codings: &[ContentCoding]) -> Option<ContentCoding> {
+        let accepted = parse_accept_encoding(accept_encoding?);
+        let q_value = |name: &str| accepted.iter().find(|(coding, _)| coding == name).map(|(_, q)| *q);
+        let any = q_value("*");
+
+        // an unlisted identity is still acceptable, but it only competes when it is listed
+        let identity = q_value("identity").or(any).unwrap_or(0.0);
+        let mut best: Option<(ContentCoding, f32)> = None;
+        for coding in codings {
+            let q = match coding {
+                ContentCoding::Gzip => q_value("gzip").or_else(|| q_value("x-gzip")),
+                ContentCoding::Deflate => q_value("deflate"),
+            }.or(any).unwrap_or(0.0);
+            if q > 0.0 && q >= identity && best.map(|(_, best)| q > best).unwrap_or(true) {
+                best = Some((*coding, q));
+            }
+        }
+        best.map(|(coding, _)| coding)
+    }
+
+    /// private: Compresses a body held in memory.
+    fn encode_bytes(&self, data: &[u8], level: flate2::Compression) -> std::io::Result<Vec<u8>> {
+        match self {
+            ContentCoding::Gzip => {
+                let mut encoder = write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+            ContentCoding::Deflate => {
+                let mut encoder = write::ZlibEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+
+    /// private: Compresses a body as it is read.
+    fn encode_reader(&self, reader: Box<dyn Read + Send>, level: flate2::Compression) -> Box<dyn Read + Send> {
+        match self {
+            ContentCoding::Gzip => Box::new(GzEncoder::new(reader, level)),
+            ContentCoding::Deflate => Box::new(ZlibEncoder::new(reader, level)),
+        }
+    }
+}
+
+impl HttpRequest {
+    /// Decodes a body sent with a `Content-Encoding` of gzip or deflate, removing the header.
+    /// Fails with `ParseError::BodyTooLarge` rather than decode more than max_size bytes, and
+    /// with `ParseError::UnsupportedContentEncoding` for any other coding.
+    pub fn decode_body(&mut self, max_size: usize) -> Result<(), ParseError> {
+        let content_encoding = match self.headers.get_combined("content-encoding") {
+            Some(content_encoding) => content_encoding,
+            None => return Ok(())
+        };
+        // a request without a body has nothing to decode, whatever it claims
+        if self.body.as_ref().map(|body| body.is_empty()).unwrap_or(true) {
+            return Ok(());
+        }
+
+        // codings are listed in the order they were applied, so undo them from the last
+        let mut data = self.body.take().unwrap_or_default();
+        for coding in content_encoding.split(',').map(|c| c.trim()).rev() {
+            let decoder: Box<dyn Read> = match coding.to_ascii_lowercase().as_str() {
+                "identity" | "" => continue,
+                "gzip" | "x-gzip" => Box::new(GzDecoder::new(&data[..])),
+                "deflate" => Box::new(ZlibDecoder::new(&data[..])),
+                _ => return Err(ParseError::UnsupportedContentEncoding(content_encoding))
+            };
+
+            let mut decoded = vec![];
+            decoder.take(max_size as u64 + 1).read_to_end(&mut decoded)
+                .map_err(|err| ParseError::InvalidContentEncoding(err.to_string()))?;
+            if decoded.len() > max_size {
+                return Err(ParseError::BodyTooLarge);
+            }
+            data = decoded;
+        }
+
+        self.headers.unset("content-encoding");
+        if self.headers.contains_key("content-length") {
+            self.headers.insert("content-length", &data.len().to_string());
+        }
+        self.body = Some(data);
+        Ok(())
+    }
+}
+
+/// Parses an `Accept-Encoding` value into lowercased codings and their q-values. Codings
+/// without a q-value get 1, and ones with a malformed q-value are skipped.
+pub fn parse_accept_encoding(value: &str) -> Vec<(String, f32)> {
+    value.split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let coding = params.next()?.trim().to_ascii_lowercase();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = match params.find_map(|p| p.trim().strip_prefix("q=").or_else(|| p.trim().strip_prefix("Q="))) {
+                Some(q) => q.parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?,
+                None => 1.0
+            };
+            Some((coding, q))
+        })
+        .collect()
+}
+
+/// private: Reads the chunks of a `HttpBody::Chunks` as one stream.
+struct ChunksReader {
+    chunks: Box<dyn Iterator<Item = Vec<u8>> + Send>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChunksReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.chunks.next() {
+                Some(chunk) => { self.chunk = chunk; self.position = 0; },
+                None => return Ok(0)
+            }
+        }
+        let size = (self.chunk.len() - self.position).min(buf.len());
+        buf[..size].copy_from_slice(&self.chunk[self.position..self.position + size]);
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// Middleware compressing response bodies with gzip or deflate, whichever the client's
+/// `Accept-Encoding` prefers. Bodies held in memory are compressed up front, and streamed
+/// bodies as they are sent, with `Transfer-Encoding: chunked`.
+///
+/// Only bodies with one of content_types are compressed, and only if they are at least
+/// min_size bytes long or of unknown length. Responses which already have a
+/// `Content-Encoding`, and partial content, are left alone. Compressible responses get
+/// `Vary: Accept-Encoding` whether or not they are compressed, and a strong `ETag` is made
+/// weak when they are, as the bytes sent differ.
+pub struct Compression {
+    /// Bodies shorter than this aren't worth compressing.
+    pub min_size: u64,
+    /// The content types which are compressed, matched against the start of `Content-Type`.
+    pub content_types: Vec<String>,
+    /// From 0, no compression, to 9, the best.
+    pub level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        let content_types = [
+            "text/", "application/json", "application/javascript", "application/xml",
+            "application/wasm", "image/svg+xml",
+        ];
+        Compression {
+            min_size: 1024,
+            content_types: content_types.iter().map(|t| t.to_string()).collect(),
+            level: 6,
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// private: Whether response is one this middleware should consider compressing. A 304
+    /// is considered as the response it stands in for, to get the same `Vary` and `ETag`.
+    fn is_compressible(&self, response: &HttpResponse) -> bool {
+        let content_type = match response.headers.get("content-type") {
+            Some(content_type) => content_type.to_ascii_lowercase(),
+            None => return false
+        };
+        let has_body = response.status_code.is_success()
+            && response.status_code.0 != 204 && response.status_code.0 != 206
+            && !matches!(response.get_body(), HttpBody::Empty);
+        (has_body || response.status_code == HttpStatusCode::NOT_MODIFIED)
+            && !response.headers.contains_key("content-encoding")
+            && self.content_types.iter().any(|t| content_type.starts_with(&t.to_ascii_lowercase()))
+    }
+
+    /// private: Makes a strong `ETag` weak, as the compressed bytes differ from the identity ones.
+    fn weaken_etag(headers: &mut HttpHeaders) {
+        if let Some(etag) = headers.get("etag").cloned() {
+            if etag.starts_with('"') {
+                headers.insert("etag", &format!("W/{}", etag));
+            }
+        }
+    }
+
+    /// private: Adds `Accept-Encoding` to the `Vary` header, unless it is already covered.
+    fn vary(headers: &mut HttpHeaders) {
+        let covered = headers.get_all("vary").iter()
+            .flat_map(|v| v.split(','))
+            .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case("accept-encoding"));
+        if !covered {
+            headers.append("vary", "Accept-Encoding");
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, request: HttpRequest, next: Next) -> HttpResponse {
+        let accept_encoding = request.headers.get_combined("accept-encoding");
+        let mut response = next(request);
+        if !self.is_compressible(&response) {
+            return response;
+        }
+        Self::vary(&mut response.headers);
+
+        let codings = [ContentCoding::Gzip, ContentCoding::Deflate];
+        if response.status_code == HttpStatusCode::NOT_MODIFIED {
+            // the body is gone, so go by whether this client would be sent a compressed one
+            if ContentCoding::negotiate(accept_encoding.as_deref(), &codings).is_some() {
+                Self::weaken_etag(&mut response.headers);
+            }
+            return response;
+        }
+
+        let length = response.get_body().content_length();
+        if length.map(|length| length < self.min_size).unwrap_or(false) {
+            return response;
+        }
+        let coding = match ContentCoding::negotiate(accept_encoding.as_deref(), &codings) {
+            Some(coding) => coding,
+            None => return response
+        };
+
+        let level = flate2::Compression::new(self.level.min(9));
+        let body = match response.take_body() {
+            HttpBody::Bytes(data) => match coding.encode_bytes(&data, level) {
+                Ok(compressed) => HttpBody::Bytes(compressed),
+                Err(_) => {
+                    response.set_body(data);
+                    return response;
+                }
+            },
+            HttpBody::Reader { reader, .. } => HttpBody::from_reader(coding.encode_reader(reader, level)),
+            HttpBody::Chunks(chunks) => {
+                let reader = ChunksReader { chunks, chunk: vec![], position: 0 };
+                HttpBody::from_reader(coding.encode_reader(Box::new(reader), level))
+            },
+            HttpBody::Empty => HttpBody::Empty,
+        };
+        response.set_body(body);
+        response.headers.insert("content-encoding", coding.as_str());
+        // byte ranges were offered into the identity body, not the compressed one
+        response.headers.unset("accept-ranges");
+        Self::weaken_etag(&mut response.headers);
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::HttpParser;
+    use crate::test_util::{self, ok};
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    fn request(accept_encoding: &str) -> HttpRequest {
+        test_util::request(&format!("GET / HTTP/1.1\r\nAccept-Encoding: {}\r\n\r\n", accept_encoding))
+    }
+
+    fn text() -> Vec<u8> {
+        "All work and no play makes Jack a dull boy. ".repeat(100).into_bytes()
+    }
+
+    fn handler(body: HttpBody, content_type: &str) -> impl Fn(HttpRequest) -> HttpResponse {
+        let body = std::sync::Mutex::new(Some(body));
+        let content_type = content_type.to_string();
+        move |_request| {
+            ok(&[("content-type", &content_type), ("etag", "\"v1\"")], body.lock().unwrap().take().unwrap())
+        }
+    }
+
+    /// The body of response, decoded.
+    fn decoded(mut response: HttpResponse) -> Vec<u8> {
+        let mut body = vec![];
+        match response.take_body() {
+            HttpBody::Bytes(data) => body = data,
+            HttpBody::Reader { mut reader, .. } => { reader.read_to_end(&mut body).unwrap(); },
+            _ => panic!("expected a body")
+        }
+
+        let mut data = vec![];
+        match response.headers.get("content-encoding").map(|c| c.as_str()) {
+            Some("gzip") => { GzDecoder::new(&body[..]).read_to_end(&mut data).unwrap(); },
+            Some("deflate") => { ZlibDecoder::new(&body[..]).read_to_end(&mut data).unwrap(); },
+            _ => data = body
+        }
+        data
+    }
+
+    #[test]
+    fn negotiate() {
+        let codings = [ContentCoding::Gzip, ContentCoding::Deflate];
+        let negotiate = |accept: &str| ContentCoding::negotiate(Some(accept), &codings);
+
+        assert_eq!(ContentCoding::negotiate(None, &codings), None);
+        assert_eq!(negotiate("gzip, deflate, br"), Some(ContentCoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(ContentCoding::Deflate));
+        assert_eq!(negotiate("x-gzip"), Some(ContentCoding::Gzip));
+        assert_eq!(negotiate("*"), Some(ContentCoding::Gzip));
+        assert_eq!(negotiate("*;q=0.5, gzip;q=0"), Some(ContentCoding::Deflate));
+        assert_eq!(negotiate("br"), None);
+        assert_eq!(negotiate(""), None);
+        assert_eq!(negotiate("gzip;q=0.5, identity"), None);
+        assert_eq!(negotiate("gzip;q=2"), None);
+        assert_eq!(negotiate("gzip;q=0.9, deflate;q=0.8"), Some(ContentCoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate;q=0.8"), Some(ContentCoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0.5, identity;q=0.8"), None);
+        assert_eq!(negotiate("gzip;q=0"), None);
+
+        assert_eq!(parse_accept_encoding("GZIP;q=0.8, *;Q=0"), vec![("gzip".to_string(), 0.8), ("*".to_string(), 0.0)]);
+    }
+
+    #[test]
+    fn compress_bytes() {
+        let response = Compression::new().handle(request("gzip"), &handler(text().into(), "text/plain"));
+        assert_eq!(response.headers.get("content-encoding"), Some(&"gzip".to_string()));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+        assert_eq!(response.headers.get("etag"), Some(&"W/\"v1\"".to_string()));
+        let length = response.get_body().content_length().unwrap();
+        assert!(length < text().len() as u64);
+        assert_eq!(response.headers.get("content-length"), Some(&length.to_string()));
+        assert_eq!(decoded(response), text());
+
+        let response = Compression::new().handle(request("deflate"), &handler(text().into(), "application/json"));
+        assert_eq!(response.headers.get("content-encoding"), Some(&"deflate".to_string()));
+        assert_eq!(decoded(response), text());
+    }
+
+    #[test]
+    fn compressed_ranges_not_offered() {
+        let next = |_request: HttpRequest| ok(&[("content-type", "text/plain"), ("accept-ranges", "bytes")], text());
+        let response = Compression::new().handle(request("gzip"), &next);
+        assert_eq!(response.headers.get("content-encoding"), Some(&"gzip".to_string()));
+        assert!(!response.headers.contains_key("accept-ranges"));
+
+        let response = Compression::new().handle(request("br"), &next);
+        assert_eq!(response.headers.get("accept-ranges"), Some(&"bytes".to_string()));
+    }
+
+    #[test]
+    fn not_modified() {
+        let next = |request: HttpRequest| {
+            ok(&[("content-type", "text/plain"), ("etag", "\"v1\"")], text()).evaluate_preconditions(&request)
+        };
+        let response = Compression::new().handle(request("gzip"), &next);
+        let etag = response.headers.get("etag").unwrap().clone();
+        assert_eq!(etag, "W/\"v1\"");
+
+        // revalidating gets a 304 with the same validators as the compressed 200
+        let request_str = format!("GET / HTTP/1.1\r\nAccept-Encoding: gzip\r\nIf-None-Match: {}\r\n\r\n", etag);
+        let response = Compression::new().handle(test_util::request(&request_str), &next);
+        assert_eq!(response.status_code, HttpStatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers.get("etag"), Some(&etag));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+        assert!(!response.headers.contains_key("content-encoding"));
+
+        let request_str = "GET / HTTP/1.1\r\nAccept-Encoding: br\r\nIf-None-Match: \"v1\"\r\n\r\n";
+        let response = Compression::new().handle(test_util::request(request_str), &next);
+        assert_eq!(response.status_code, HttpStatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers.get("etag"), Some(&"\"v1\"".to_string()));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+    }
+
+    #[test]
+    fn compress_streams() {
+        let chunks = HttpBody::from_chunks(vec![b"streamed ".to_vec(), b"body".to_vec()]);
+        let response = Compression::new().handle(request("gzip"), &handler(chunks, "text/plain"));
+        assert!(response.is_chunked());
+        assert_eq!(response.headers.get("content-encoding"), Some(&"gzip".to_string()));
+
+        assert!(matches!(response.get_body(), HttpBody::Reader { length: None, .. }));
+        assert_eq!(decoded(response), b"streamed body");
+    }
+
+    #[test]
+    fn leave_alone() {
+        // too small, but still varies
+        let response = Compression::new().handle(request("gzip"), &handler(b"tiny".to_vec().into(), "text/plain"));
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+
+        // not a compressible type
+        let response = Compression::new().handle(request("gzip"), &handler(text().into(), "image/png"));
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert!(!response.headers.contains_key("vary"));
+
+        // not accepted
+        let response = Compression::new().handle(request("br"), &handler(text().into(), "text/plain"));
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.headers.get("etag"), Some(&"\"v1\"".to_string()));
+        assert_eq!(decoded(response), text());
+    }
+
+    #[test]
+    fn decode_request_body() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"deflated").unwrap();
+        let body = encoder.finish().unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&body).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let head = format!("POST / HTTP/1.1\r\nContent-Encoding: deflate, gzip\r\nContent-Length: {}\r\n\r\n", body.len());
+        let mut request_bytes = head.into_bytes();
+        request_bytes.extend_from_slice(&body);
+        let mut request = HttpParser::new(&request_bytes[..]).parse_http_request().unwrap();
+
+        assert!(matches!(request.clone().decode_body(4), Err(ParseError::BodyTooLarge)));
+        request.decode_body(1024).unwrap();
+        assert_eq!(request.body, Some(b"deflated".to_vec()));
+        assert_eq!(request.headers.get("content-length"), Some(&"8".to_string()));
+        assert!(!request.headers.contains_key("content-encoding"));
+
+        let mut request = test_util::request("GET / HTTP/1.1\r\nContent-Encoding: gzip\r\n\r\n");
+        request.decode_body(1024).unwrap();
+        assert_eq!(request.body, None);
+    }
+}
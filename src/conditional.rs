@@ -0,0 +1,258 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::date::{format_http_date, parse_http_date};
+use crate::request::{HttpRequest, HttpResponse, HttpMethod, HttpStatusCode, HttpHeaders, HttpBody};
+
+/// An entity-tag, as sent in `ETag` and compared against `If-Match` and `If-None-Match`.
+/// A weak tag only promises the representations are equivalent, not byte for byte identical.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EntityTag {
+    pub weak: bool,
+    /// The opaque tag, without its quotes.
+    pub tag: String,
+}
+
+impl EntityTag {
+    pub fn strong(tag: &str) -> Self {
+        EntityTag { weak: false, tag: tag.to_string() }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        EntityTag { weak: true, tag: tag.to_string() }
+    }
+
+    /// Strong comparison: both tags are strong, and the same.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: the tags are the same, whether or not either is weak.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses a comma separated list of entity-tags, skipping any which are malformed.
+    pub fn parse_list(list: &str) -> Vec<EntityTag> {
+        let mut tags = vec![];
+        let mut rest = list;
+        loop {
+            rest = rest.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+            if rest.is_empty() {
+                return tags;
+            }
+
+            // a tag may contain commas, so find where it ends by its closing quote
+            let start = if rest.starts_with("W/\"") { 3 } else { 1 };
+            let end = rest.get(start..).and_then(|r| r.find('"')).map(|end| start + end + 1);
+            let (tag, next) = match end {
+                Some(end) if rest[start - 1..].starts_with('"') => rest.split_at(end),
+                _ => rest.split_at(rest.find(',').unwrap_or(rest.len()))
+            };
+            if let Ok(tag) = EntityTag::try_from(tag) {
+                tags.push(tag);
+            }
+            rest = next;
+        }
+    }
+}
+
+impl TryFrom<&str> for EntityTag {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, value)
+        };
+        let tag = quoted.strip_prefix('"')
+            .and_then(|q| q.strip_suffix('"'))
+            .ok_or(format!("entity-tag must be quoted: {}", value))?;
+        // etagc = %x21 / %x23-7E / obs-text
+        if tag.bytes().any(|b| b == b'"' || b <= b' ' || b == 0x7f) {
+            return Err(format!("invalid entity-tag: {}", value));
+        }
+        Ok(EntityTag { weak, tag: tag.to_string() })
+    }
+}
+
+impl fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl HttpResponse {
+    /// Sets the `ETag` of the representation in this response.
+    pub fn set_etag(&mut self, etag: &EntityTag) {
+        self.headers.insert("etag", &etag.to_string());
+    }
+
+    /// Sets the `Last-Modified` date of the representation in this response.
+    pub fn set_last_modified(&mut self, time: SystemTime) {
+        self.headers.insert("last-modified", &format_http_date(time));
+    }
+
+    /// Evaluates the preconditions of request against the `ETag` and `Last-Modified` of this
+    /// response, in the order of RFC 9110 section 13.2.2. Returns this response if they pass,
+    /// otherwise a `304 Not Modified` for a GET or HEAD, or a `412 Precondition Failed`.
+    ///
+    /// Preconditions are only evaluated for 2xx responses, anything else is returned as is.
+    pub fn evaluate_preconditions(mut self, request: &HttpRequest) -> HttpResponse {
+        if !self.status_code.is_success() {
+            return self;
+        }
+
+        let headers = &request.headers;
+        let etag = self.headers.get("etag").and_then(|etag| EntityTag::try_from(etag.as_str()).ok());
+        let last_modified = self.headers.get("last-modified").and_then(|date| parse_http_date(date));
+        let is_get = matches!(request.method, HttpMethod::GET | HttpMethod::HEAD);
+
+        // 1. If-Match, otherwise 2. If-Unmodified-Since
+        if let Some(if_match) = headers.get_combined("if-match") {
+            let matches = if_match.trim() == "*" || etag.as_ref()
+                .map(|etag| EntityTag::parse_list(&if_match).iter().any(|tag| tag.strong_eq(etag)))
+                .unwrap_or(false);
+            if !matches {
+                return HttpResponse::error(HttpStatusCode::PRECONDITION_FAILED, HttpHeaders::new());
+            }
+        } else if let (Some(since), Some(last_modified)) = (Self::date_header(headers, "if-unmodified-since"), last_modified) {
+            if last_modified > since {
+                return HttpResponse::error(HttpStatusCode::PRECONDITION_FAILED, HttpHeaders::new());
+            }
+        }
+
+        // 3. If-None-Match, otherwise 4. If-Modified-Since
+        let modified = if let Some(if_none_match) = headers.get_combined("if-none-match") {
+            if_none_match.trim() != "*" && !etag.as_ref()
+                .map(|etag| EntityTag::parse_list(&if_none_match).iter().any(|tag| tag.weak_eq(etag)))
+                .unwrap_or(false)
+        } else if let (true, Some(since), Some(last_modified)) = (is_get, Self::date_header(headers, "if-modified-since"), last_modified) {
+            last_modified > since
+        } else {
+            true
+        };
+        if modified {
+            return self;
+        }
+        if !is_get {
+            return HttpResponse::error(HttpStatusCode::PRECONDITION_FAILED, HttpHeaders::new());
+        }
+
+        // the client's copy is current, so send the validators without the representation.
+        // The Content-Type stays for middleware to go by, the server leaves it out when sending
+        self.status_code = HttpStatusCode::NOT_MODIFIED;
+        self.reason_phrase = None;
+        self.set_body(HttpBody::Empty);
+        self
+    }
+
+    /// private: A header holding an HTTP-date, ignoring it if the date is invalid.
+    fn date_header(headers: &HttpHeaders, key: &str) -> Option<SystemTime> {
+        headers.get(key).and_then(|date| parse_http_date(date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{request, ok};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    /// A 200 for a representation tagged "v2", last modified at Sun, 06 Nov 1994 08:49:37 GMT.
+    fn response() -> HttpResponse {
+        let mut response = ok(&[("content-type", "text/plain")], Some(b"hello".to_vec()));
+        response.set_etag(&EntityTag::strong("v2"));
+        response.set_last_modified(UNIX_EPOCH + Duration::from_secs(784111777));
+        response
+    }
+
+    fn status(request_str: &str) -> HttpStatusCode {
+        response().evaluate_preconditions(&request(request_str)).status_code
+    }
+
+    #[test]
+    fn entity_tags() {
+        assert_eq!(EntityTag::try_from("\"v1\""), Ok(EntityTag::strong("v1")));
+        assert_eq!(EntityTag::try_from("W/\"v1\""), Ok(EntityTag::weak("v1")));
+        assert!(EntityTag::try_from("v1").is_err());
+        assert_eq!(EntityTag::weak("v1").to_string(), "W/\"v1\"");
+
+        assert!(EntityTag::strong("v1").strong_eq(&EntityTag::strong("v1")));
+        assert!(!EntityTag::strong("v1").strong_eq(&EntityTag::weak("v1")));
+        assert!(EntityTag::strong("v1").weak_eq(&EntityTag::weak("v1")));
+
+        assert_eq!(
+            EntityTag::parse_list("\"a,b\", W/\"c\",bad, \"d\""),
+            vec![EntityTag::strong("a,b"), EntityTag::weak("c"), EntityTag::strong("d")]
+        );
+    }
+
+    #[test]
+    fn if_none_match() {
+        let response = response().evaluate_preconditions(&request("GET / HTTP/1.1\r\nIf-None-Match: \"v1\", W/\"v2\"\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers.get("etag"), Some(&"\"v2\"".to_string()));
+        assert!(matches!(response.get_body(), HttpBody::Empty));
+
+        assert_eq!(status("GET / HTTP/1.1\r\nIf-None-Match: \"v1\"\r\n\r\n"), HttpStatusCode::OK);
+        assert_eq!(status("HEAD / HTTP/1.1\r\nIf-None-Match: *\r\n\r\n"), HttpStatusCode::NOT_MODIFIED);
+        assert_eq!(status("DELETE / HTTP/1.1\r\nIf-None-Match: *\r\n\r\n"), HttpStatusCode::PRECONDITION_FAILED);
+    }
+
+    #[test]
+    fn if_modified_since() {
+        assert_eq!(status("GET / HTTP/1.1\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"), HttpStatusCode::NOT_MODIFIED);
+        assert_eq!(status("GET / HTTP/1.1\r\nIf-Modified-Since: Sat, 05 Nov 1994 08:49:37 GMT\r\n\r\n"), HttpStatusCode::OK);
+        assert_eq!(status("GET / HTTP/1.1\r\nIf-Modified-Since: garbage\r\n\r\n"), HttpStatusCode::OK);
+        assert_eq!(status("GET / HTTP/1.1\r\nIf-Modified-Since: Sun, 06 Nov 999999999999999999 08:49:37 GMT\r\n\r\n"), HttpStatusCode::OK);
+        // only for GET and HEAD
+        assert_eq!(status("DELETE / HTTP/1.1\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"), HttpStatusCode::OK);
+        // If-None-Match takes precedence
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nIf-None-Match: \"v1\"\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"),
+            HttpStatusCode::OK
+        );
+    }
+
+    #[test]
+    fn if_match_and_if_unmodified_since() {
+        assert_eq!(status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Match: \"v2\"\r\n\r\n"), HttpStatusCode::OK);
+        assert_eq!(status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Match: *\r\n\r\n"), HttpStatusCode::OK);
+        assert_eq!(status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Match: \"v1\"\r\n\r\n"), HttpStatusCode::PRECONDITION_FAILED);
+        // If-Match uses the strong comparison
+        assert_eq!(status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Match: W/\"v2\"\r\n\r\n"), HttpStatusCode::PRECONDITION_FAILED);
+
+        assert_eq!(
+            status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Unmodified-Since: Sat, 05 Nov 1994 08:49:37 GMT\r\n\r\n"),
+            HttpStatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(
+            status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Unmodified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n\r\n"),
+            HttpStatusCode::OK
+        );
+        // If-Match takes precedence
+        assert_eq!(
+            status("PUT / HTTP/1.1\r\nContent-Length: 0\r\nIf-Match: \"v2\"\r\nIf-Unmodified-Since: Sat, 05 Nov 1994 08:49:37 GMT\r\n\r\n"),
+            HttpStatusCode::OK
+        );
+        // and both come before If-None-Match
+        assert_eq!(
+            status("GET / HTTP/1.1\r\nIf-Match: \"v1\"\r\nIf-None-Match: \"v2\"\r\n\r\n"),
+            HttpStatusCode::PRECONDITION_FAILED
+        );
+    }
+
+    #[test]
+    fn only_for_success() {
+        let mut response = response();
+        response.status_code = HttpStatusCode::NOT_FOUND;
+        let response = response.evaluate_preconditions(&request("GET / HTTP/1.1\r\nIf-None-Match: *\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_FOUND);
+    }
+}
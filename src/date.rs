@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats time as an HTTP-date, such as `Sun, 06 Nov 1994 08:49:37 GMT`. Fractions of a
+/// second are dropped, and times before 1970 are formatted as 1970.
+pub fn format_http_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let days = (secs / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let secs = secs % 86400;
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[((days + 4) % 7) as usize], day, MONTHS[month as usize - 1], year,
+        secs / 3600, secs / 60 % 60, secs % 60
+    )
+}
+
+/// Parses an HTTP-date in any of the three formats recipients must accept: the IMF-fixdate
+/// `Sun, 06 Nov 1994 08:49:37 GMT`, the obsolete RFC 850 `Sunday, 06-Nov-94 08:49:37 GMT` and
+/// asctime `Sun Nov  6 08:49:37 1994`. Returns `None` for anything else.
+pub fn parse_http_date(date: &str) -> Option<SystemTime> {
+    let fields: Vec<&str> = date.split_whitespace().collect();
+    let (day, month, year, time) = match fields.as_slice() {
+        [weekday, day, month, year, time, "GMT"] if weekday.ends_with(',') => (*day, *month, year.parse().ok()?, *time),
+        [weekday, date, time, "GMT"] if weekday.ends_with(',') => {
+            let mut date = date.split('-');
+            let (day, month, year) = (date.next()?, date.next()?, date.next()?);
+            if date.next().is_some() || year.len() != 2 {
+                return None;
+            }
+            // two digit years which look more than 50 years in the future are in the past
+            let year: i64 = year.parse().ok()?;
+            (day, month, if year < 70 { 2000 + year } else { 1900 + year }, *time)
+        },
+        [_, month, day, time, year] => (*day, *month, year.parse().ok()?, *time),
+        _ => return None
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let mut time = time.split(':').map(|t| t.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    // the year comes from the client, so keep it to four digits rather than overflow on it
+    if time.next().is_some() || hour > 23 || minute > 59 || second > 60 || !(1970..=9999).contains(&year) {
+        return None;
+    }
+    // a day past the end of the month would silently roll over into the next one
+    if day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day) as u64;
+    let secs = days.checked_mul(86400)?.checked_add(hour * 3600 + minute * 60 + second)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// private: The number of days in month of year, in the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// private: The number of days from 1970-01-01 to the given proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month as i64 + 9) % 12) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// private: The proleptic Gregorian (year, month, day) a number of days after 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_dates() {
+        let time = UNIX_EPOCH + Duration::from_secs(784111777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(time));
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Some(time));
+        assert_eq!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"), Some(UNIX_EPOCH + Duration::from_secs(951782400)));
+
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 PST"), None);
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 25:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("yesterday"), None);
+        assert_eq!(parse_http_date("Sat, 31 Feb 2024 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Thu, 29 Feb 2023 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Mon, 29 Feb 2100 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sat, 31 Apr 2024 00:00:00 GMT"), None);
+        assert_eq!(parse_http_date("Sun, 00 Nov 1994 08:49:37 GMT"), None);
+        assert!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT").is_some());
+        assert_eq!(parse_http_date("Sun, 06 Nov 999999999999999999 08:49:37 GMT"), None);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 10000"), None);
+        assert!(parse_http_date("Fri, 31 Dec 9999 23:59:59 GMT").is_some());
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::compress::ContentCoding;
+use crate::conditional::EntityTag;
+use crate::request::{HttpResponse, HttpVersion, HttpStatusCode, HttpHeaders, HttpBody};
+
+/// The `Content-Type` of a file, going by its extension. Unknown extensions are served as
+/// `application/octet-stream`.
+pub fn mime_type(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "xml" => "application/xml",
+        "json" => "application/json",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Serves the files under a directory. Paths are resolved relative to the root, a directory
+/// is served by its `index.html`, and nothing outside the root is ever served: `..`
+/// segments and symlinks pointing out of the root are answered with a 403.
+///
+/// Responses carry an `ETag` and `Last-Modified` taken from the file's metadata, so that
+/// `HttpResponse::evaluate_preconditions` can answer conditional requests for them.
+///
+/// Mount it on a Router with `Router::files`, or call `serve` from a handler.
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    /// Serve a file's gzipped sibling, `app.js.gz` for `app.js`, in its place to clients
+    /// which accept gzip.
+    pub precompressed: bool,
+}
+
+impl StaticFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        StaticFiles { root: root.into(), precompressed: false }
+    }
+
+    /// Answers a request for the file at path, relative to the root. Both `/` and `\` are
+    /// taken as separators.
+    pub fn serve(&self, path: &str) -> HttpResponse {
+        self.serve_encoded(path, None)
+    }
+
+    /// Like `serve`, but when precompressed, answers with the file's `.gz` sibling if there is
+    /// one and accept_encoding, the request's `Accept-Encoding`, allows gzip.
+    pub fn serve_encoded(&self, path: &str, accept_encoding: Option<&str>) -> HttpResponse {
+        let mut file = self.root.clone();
+        for segment in path.split(['/', '\\']) {
+            match segment {
+                "" | "." => continue,
+                ".." => return HttpResponse::error(HttpStatusCode::FORBIDDEN, HttpHeaders::new()),
+                // a drive prefix would replace the path pushed onto
+                _ if segment.contains(['\0', ':']) => return HttpResponse::error(HttpStatusCode::NOT_FOUND, HttpHeaders::new()),
+                _ => file.push(segment)
+            }
+        }
+
+        match self.open(&file, accept_encoding) {
+            Ok(response) => response,
+            Err(status_code) => HttpResponse::error(status_code, HttpHeaders::new())
+        }
+    }
+
+    /// private: Opens the file at path, or the index.html of the directory at path.
+    fn open(&self, path: &Path, accept_encoding: Option<&str>) -> Result<HttpResponse, HttpStatusCode> {
+        let mut path = self.resolve(path)?;
+        if path.is_dir() {
+            path = self.resolve(&path.join("index.html"))?;
+        }
+        if !path.is_file() {
+            return Err(HttpStatusCode::NOT_FOUND);
+        }
+
+        let mut headers = HttpHeaders::new();
+        headers.insert("content-type", mime_type(&path));
+        if let Some(gzipped) = self.gzipped(&path) {
+            headers.insert("vary", "Accept-Encoding");
+            if ContentCoding::negotiate(accept_encoding, &[ContentCoding::Gzip]).is_some() {
+                headers.insert("content-encoding", "gzip");
+                path = gzipped;
+            }
+        }
+
+        let file = File::open(&path).map_err(Self::error_status)?;
+        let metadata = file.metadata().map_err(Self::error_status)?;
+        let length = metadata.len();
+
+        let body = HttpBody::Reader { reader: Box::new(file), length: Some(length) };
+        let mut response = HttpResponse::new(HttpVersion::default(), HttpStatusCode::OK, headers, body);
+
+        // any change to the file changes its size or modification time, as far as we can tell
+        if let Ok(modified) = metadata.modified() {
+            let nanos = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+            response.set_etag(&EntityTag::strong(&format!("{:x}-{:x}", length, nanos)));
+            response.set_last_modified(modified);
+        }
+        Ok(response)
+    }
+
+    /// private: The gzipped sibling of the file at path, when precompressed and there is one.
+    fn gzipped(&self, path: &Path) -> Option<PathBuf> {
+        if !self.precompressed {
+            return None;
+        }
+        let mut gzipped = path.as_os_str().to_owned();
+        gzipped.push(".gz");
+        self.resolve(Path::new(&gzipped)).ok().filter(|gzipped| gzipped.is_file())
+    }
+
+    /// private: Follows any symlinks in path, making sure it still ends up under the root.
+    fn resolve(&self, path: &Path) -> Result<PathBuf, HttpStatusCode> {
+        let root = self.root.canonicalize().map_err(Self::error_status)?;
+        let path = path.canonicalize().map_err(Self::error_status)?;
+        if !path.starts_with(&root) {
+            return Err(HttpStatusCode::FORBIDDEN);
+        }
+        Ok(path)
+    }
+
+    fn error_status(err: std::io::Error) -> HttpStatusCode {
+        match err.kind() {
+            ErrorKind::NotFound | ErrorKind::NotADirectory => HttpStatusCode::NOT_FOUND,
+            ErrorKind::PermissionDenied => HttpStatusCode::FORBIDDEN,
+            _ => HttpStatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{request, body};
+    use std::fs;
+
+    /// A directory of files to serve, removed again once dropped.
+    struct TestRoot(PathBuf);
+
+    impl TestRoot {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("http-files-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join("public/docs")).unwrap();
+            fs::write(dir.join("public/index.html"), "<h1>index</h1>").unwrap();
+            fs::write(dir.join("public/style.css"), "body {}").unwrap();
+            fs::write(dir.join("public/docs/notes.txt"), "notes").unwrap();
+            fs::write(dir.join("secret.txt"), "secret").unwrap();
+            TestRoot(dir)
+        }
+
+        fn files(&self) -> StaticFiles {
+            StaticFiles::new(self.0.join("public"))
+        }
+    }
+
+    impl Drop for TestRoot {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn mime_types() {
+        assert_eq!(mime_type(Path::new("index.HTML")), "text/html; charset=utf-8");
+        assert_eq!(mime_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(mime_type(Path::new("archive.tar.gz")), "application/gzip");
+        assert_eq!(mime_type(Path::new("Makefile")), "application/octet-stream");
+    }
+
+    #[test]
+    fn serve_files() {
+        let root = TestRoot::new("serve");
+        let files = root.files();
+
+        let response = files.serve("style.css");
+        assert_eq!(response.status_code, HttpStatusCode::OK);
+        assert_eq!(response.headers.get("content-type"), Some(&"text/css; charset=utf-8".to_string()));
+        assert_eq!(response.get_body().content_length(), Some(7));
+        assert_eq!(body(response), "body {}");
+
+        assert_eq!(body(files.serve("docs/notes.txt")), "notes");
+        assert_eq!(body(files.serve("")), "<h1>index</h1>");
+        assert_eq!(files.serve("docs").status_code, HttpStatusCode::NOT_FOUND);
+        assert_eq!(files.serve("missing.txt").status_code, HttpStatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn serve_nothing_outside_root() {
+        let root = TestRoot::new("traversal");
+        let files = root.files();
+
+        assert_eq!(files.serve("../secret.txt").status_code, HttpStatusCode::FORBIDDEN);
+        assert_eq!(files.serve("docs/../../secret.txt").status_code, HttpStatusCode::FORBIDDEN);
+        assert_eq!(files.serve("..\\secret.txt").status_code, HttpStatusCode::FORBIDDEN);
+        assert_eq!(files.serve("/etc/passwd").status_code, HttpStatusCode::NOT_FOUND);
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.0.join("secret.txt"), root.0.join("public/link.txt")).unwrap();
+            assert_eq!(files.serve("link.txt").status_code, HttpStatusCode::FORBIDDEN);
+        }
+    }
+
+    #[test]
+    fn serve_precompressed() {
+        use std::io::Write;
+        let root = TestRoot::new("precompressed");
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"body {}").unwrap();
+        fs::write(root.0.join("public/style.css.gz"), encoder.finish().unwrap()).unwrap();
+
+        let mut files = root.files();
+        assert!(!files.serve_encoded("style.css", Some("gzip")).headers.contains_key("content-encoding"));
+
+        files.precompressed = true;
+        let response = files.serve_encoded("style.css", Some("gzip, deflate"));
+        assert_eq!(response.headers.get("content-encoding"), Some(&"gzip".to_string()));
+        assert_eq!(response.headers.get("content-type"), Some(&"text/css; charset=utf-8".to_string()));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+        assert_ne!(response.get_body().content_length(), Some(7));
+
+        let response = files.serve_encoded("style.css", Some("gzip;q=0.8, br;q=0.9"));
+        assert_eq!(response.headers.get("content-encoding"), Some(&"gzip".to_string()));
+
+        let response = files.serve_encoded("style.css", Some("br"));
+        assert!(!response.headers.contains_key("content-encoding"));
+        assert_eq!(response.headers.get("vary"), Some(&"Accept-Encoding".to_string()));
+        assert_eq!(body(response), "body {}");
+
+        // without a sibling, there is nothing to vary
+        assert!(!files.serve_encoded("docs/notes.txt", Some("gzip")).headers.contains_key("vary"));
+    }
+
+    #[test]
+    fn mounted_on_router() {
+        let root = TestRoot::new("router");
+        let mut router = crate::router::Router::new();
+        router.files("/static/", root.files());
+
+        assert_eq!(body(router.handle(request("GET /static/docs/notes.txt HTTP/1.1\r\n\r\n"))), "notes");
+        assert_eq!(body(router.handle(request("GET /static HTTP/1.1\r\n\r\n"))), "<h1>index</h1>");
+        let response = router.handle(request("GET /static/%2e%2e/secret.txt HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::FORBIDDEN);
+        let response = router.handle(request("GET /static/..%2fsecret.txt HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::FORBIDDEN);
+
+        let response = router.handle(request("GET /static/style.css HTTP/1.1\r\n\r\n"));
+        let etag = response.headers.get("etag").unwrap().clone();
+        let last_modified = response.headers.get("last-modified").unwrap().clone();
+        let response = router.handle(request(&format!("GET /static/style.css HTTP/1.1\r\nIf-None-Match: {}\r\n\r\n", etag)));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_MODIFIED);
+        let response = router.handle(request(&format!("GET /static/style.css HTTP/1.1\r\nIf-Modified-Since: {}\r\n\r\n", last_modified)));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_MODIFIED);
+
+        let response = router.handle(request("GET /static/style.css HTTP/1.1\r\nRange: bytes=5-\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response), "{}");
+    }
+}
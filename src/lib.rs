@@ -1,27 +1,92 @@
+mod compress;
+mod conditional;
+mod date;
+mod files;
+mod middleware;
 mod parser;
+mod pool;
+mod range;
 mod request;
+mod router;
 mod stream;
 mod server;
+mod shutdown;
+mod uri;
+#[cfg(test)]
+mod test_util;
 
+pub use compress::*;
+pub use conditional::*;
+pub use date::*;
+pub use files::*;
+pub use middleware::*;
 pub use request::*;
+pub use router::*;
 pub use parser::*;
+pub use pool::*;
+pub use range::*;
 pub use stream::*;
 pub use server::*;
+pub use shutdown::*;
+pub use uri::*;
 
 /// tests: test using threads, so that we can send network requests while listening for network
 /// requests!
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
     #[test]
     fn it_works() {
-        // let port = 8080;
-
-        // start the server
-        // listen(port);
+        // start the server, on any free port
+        let mut server = HttpServer::new();
+        let addrs = server.bind("127.0.0.1:0").unwrap();
+        let shutdown = server.shutdown_handle();
+        let server_thread = thread::spawn(move || server.serve());
 
         // execute requests against the server
-        // let stream = TcpStream::connect(8080);
-        // let message = b"Hello, World!";
-        // stream.write(message).unwrap();
+        let mut stream = TcpStream::connect(addrs[0]).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("<h1>Hello, World!</h1>"));
+
+        // and stop it
+        shutdown.shutdown();
+        server_thread.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn serves_several_listeners() {
+        let mut server = HttpServer::new();
+        server.multi_threaded = false;
+        let mut addrs = server.bind("0.0.0.0:0").unwrap();
+        addrs.push(server.add_listener(TcpListener::bind("127.0.0.1:0").unwrap()).unwrap());
+        if let Ok(v6) = server.bind("[::1]:0") {
+            addrs.extend(v6);
+        }
+        assert_eq!(server.local_addrs(), addrs);
+        assert!(addrs.iter().all(|addr| addr.port() != 0));
+
+        let shutdown = server.shutdown_handle();
+        let server_thread = thread::spawn(move || server.serve());
+
+        for mut addr in addrs {
+            if addr.ip().is_unspecified() {
+                addr.set_ip([127, 0, 0, 1].into());
+            }
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        }
+
+        shutdown.shutdown();
+        server_thread.join().unwrap().unwrap();
     }
 }
@@ -1,43 +1,31 @@
 use http::*;
-use std::path::PathBuf;
-use std::fs;
 
 fn main() -> std::io::Result<()> {
 
     let mut server = HttpServer::new();
+    let mut router = Router::new();
+    let pages = StaticFiles::new("pages");
 
-    server.request_handler = move |req| -> HttpResponse {
+    router.get("/", |_req| {
         let body = b"<h1>Big boy time</h1>".to_vec();
-        let not_found_body = b"<h1>404 Not Found</h1>".to_vec();
+        HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode::OK,
+            HttpHeaders::default(),
+            Some(body)
+        )
+    });
 
-        let path = std::path::PathBuf::from(req.uri);
+    let home = pages.clone();
+    router.get("/home", move |req| home.serve("home.html").evaluate_preconditions(&req).apply_range(&req));
+    router.files("/pages", pages);
 
-        if path == PathBuf::new().join("/") {
-            HttpResponse::new(
-                HttpVersion::default(),
-                HttpStatusCode(200),
-                HttpHeaders::default(),
-                Some(body)
-            )
-        } else if path == PathBuf::new().join("/home") {
-            let page = fs::read(PathBuf::from("pages/home.html")).unwrap();
-            HttpResponse::new(
-                HttpVersion::default(),
-                HttpStatusCode(200),
-                HttpHeaders::default(),
-                Some(page)
-            )
-        } else {
-            HttpResponse::new(
-                HttpVersion::default(),
-                HttpStatusCode(404),
-                HttpHeaders::default(),
-                Some(not_found_body)
-            )
-        }
-    };
+    server.request_handler = router.into_handler();
+    server.add_middleware(Compression::new());
 
-    server.listen(8080).unwrap();
+    let shutdown = server.shutdown_handle();
+    ctrlc::set_handler(move || shutdown.shutdown())
+        .map_err(std::io::Error::other)?;
 
-    Ok(())
-}
\ No newline at end of file
+    server.listen(8080)
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use crate::request::{HttpRequest, HttpResponse};
+use crate::server::RequestHandler;
+
+/// The rest of the chain after a middleware: the following middleware, then the handler.
+pub type Next<'a> = &'a dyn Fn(HttpRequest) -> HttpResponse;
+
+/// Wraps request handlers with cross-cutting behaviour. A middleware can inspect or change the
+/// request before calling `next`, inspect or change the response `next` returns, or answer
+/// without calling `next` at all.
+///
+/// Closures taking `(HttpRequest, Next)` are middleware too.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: HttpRequest, next: Next) -> HttpResponse;
+}
+
+impl<F> Middleware for F
+    where F: Fn(HttpRequest, Next) -> HttpResponse + Send + Sync
+{
+    fn handle(&self, request: HttpRequest, next: Next) -> HttpResponse {
+        self(request, next)
+    }
+}
+
+/// Runs request through middleware, in order, and then handler.
+pub(crate) fn run(middleware: &[Arc<dyn Middleware>], request: HttpRequest, handler: Next) -> HttpResponse {
+    match middleware.split_first() {
+        Some((first, rest)) => first.handle(request, &|request| run(rest, request, handler)),
+        None => handler(request)
+    }
+}
+
+/// Gets a handler which runs middleware, in order, around handler.
+pub(crate) fn wrap(handler: Arc<RequestHandler>, middleware: &[Arc<dyn Middleware>]) -> Arc<RequestHandler> {
+    if middleware.is_empty() {
+        return handler;
+    }
+    let middleware = middleware.to_vec();
+    Arc::new(move |request| run(&middleware, request, &*handler))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::{HttpVersion, HttpStatusCode, HttpHeaders};
+    use crate::test_util::{request, ok};
+
+    /// Answers with the `x-trace` request header as its body.
+    fn handler(req: HttpRequest) -> HttpResponse {
+        let trace = req.headers.get("x-trace").cloned().unwrap_or_default();
+        ok(&[], Some(trace.into_bytes()))
+    }
+
+    /// Appends name to the `x-trace` header of the request, and of the response.
+    fn tracer(name: &'static str) -> Arc<dyn Middleware> {
+        Arc::new(move |mut req: HttpRequest, next: Next| {
+            let trace = req.headers.get("x-trace").cloned().unwrap_or_default();
+            req.headers.insert("x-trace", &format!("{}{}", trace, name));
+            let mut res = next(req);
+            let trace = res.headers.get("x-trace").cloned().unwrap_or_default();
+            res.headers.insert("x-trace", &format!("{}{}", trace, name));
+            res
+        })
+    }
+
+    #[test]
+    fn runs_in_order() {
+        let handler = wrap(Arc::new(handler), &[tracer("a"), tracer("b")]);
+        let response = handler(request("GET / HTTP/1.1\r\n\r\n"));
+
+        assert_eq!(response.headers.get("x-trace"), Some(&"ba".to_string()));
+        assert!(String::from(response).ends_with("\r\n\r\nab"));
+    }
+
+    #[test]
+    fn short_circuits() {
+        let deny: Arc<dyn Middleware> = Arc::new(|_req: HttpRequest, _next: Next| {
+            HttpResponse::new(HttpVersion::default(), HttpStatusCode::UNAUTHORIZED, HttpHeaders::new(), None)
+        });
+        let handler = wrap(Arc::new(handler), &[tracer("a"), deny, tracer("b")]);
+        let response = handler(request("GET / HTTP/1.1\r\n\r\n"));
+
+        assert_eq!(response.status_code, HttpStatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers.get("x-trace"), Some(&"a".to_string()));
+    }
+}
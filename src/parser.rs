@@ -1,82 +1,311 @@
 use std::io::{BufRead};
-use std::collections::HashMap;
 use std::convert::{TryInto};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fmt;
 
-use crate::request::{HttpRequest, HttpMethod};
+use crate::request::{HttpRequest, HttpMethod, HttpVersion, HttpHeaders};
 use crate::request;
+use crate::uri::RequestTarget;
+
+const N: u8 = b'\n';
+const RN: [u8; 2] = [b'\r', b'\n'];
+
+/// Errors which can occur while parsing an HTTP request.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The Request-Line was not of the form `Method SP Request-URI SP HTTP-Version`.
+    BadRequestLine(String),
+    /// The method was not a valid token.
+    UnknownMethod(String),
+    /// The HTTP-Version was well formed, but is not one we can speak.
+    BadVersion(String),
+    /// A header line was missing its `:` separator, or had an empty name.
+    MalformedHeader(String),
+    /// The method requires a body, but no `Content-Length` was sent.
+    MissingContentLength,
+    /// The `Content-Length` header could not be parsed as a length.
+    InvalidContentLength(String),
+    /// Both `Content-Length` and `Transfer-Encoding` were sent, so the body length is ambiguous.
+    ConflictingLength,
+    /// The `Transfer-Encoding` header named a coding other than `chunked`.
+    UnsupportedTransferEncoding(String),
+    /// A chunk of a chunked body was malformed.
+    InvalidChunk(String),
+    /// The Request-Line was longer than `ParseLimits::max_request_line`.
+    RequestLineTooLong,
+    /// There were more header fields, or more header bytes, than `ParseLimits` allows.
+    HeadersTooLarge,
+    /// The body was longer than `ParseLimits::max_body_size`.
+    BodyTooLarge,
+    /// The client took too long to send the request.
+    Timeout,
+    /// The `Expect` header held an expectation other than `100-continue`.
+    ExpectationFailed(String),
+    /// The `Content-Encoding` of the body named a coding we can't decode.
+    UnsupportedContentEncoding(String),
+    /// The body could not be decoded with its `Content-Encoding`.
+    InvalidContentEncoding(String),
+    /// The underlying reader failed.
+    Io(std::io::Error),
+    /// The reader ended before a complete request was read.
+    Eof,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::BadRequestLine(line) => write!(f, "bad request line: {:?}", line),
+            ParseError::UnknownMethod(method) => write!(f, "invalid method: {}", method),
+            ParseError::BadVersion(version) => write!(f, "unsupported http version: {}", version),
+            ParseError::MalformedHeader(line) => write!(f, "malformed header: {:?}", line),
+            ParseError::MissingContentLength => write!(f, "missing content-length"),
+            ParseError::InvalidContentLength(value) => write!(f, "invalid content-length: {:?}", value),
+            ParseError::ConflictingLength => write!(f, "both content-length and transfer-encoding were sent"),
+            ParseError::UnsupportedTransferEncoding(value) => write!(f, "unsupported transfer-encoding: {}", value),
+            ParseError::InvalidChunk(line) => write!(f, "invalid chunk: {:?}", line),
+            ParseError::RequestLineTooLong => write!(f, "request line too long"),
+            ParseError::HeadersTooLarge => write!(f, "header fields too large"),
+            ParseError::BodyTooLarge => write!(f, "body too large"),
+            ParseError::Timeout => write!(f, "timed out reading request"),
+            ParseError::ExpectationFailed(value) => write!(f, "unsupported expectation: {}", value),
+            ParseError::UnsupportedContentEncoding(value) => write!(f, "unsupported content-encoding: {}", value),
+            ParseError::InvalidContentEncoding(err) => write!(f, "invalid content-encoding: {}", err),
+            ParseError::Io(err) => write!(f, "io error: {}", err),
+            ParseError::Eof => write!(f, "unexpected end of stream"),
+        }
+    }
+}
 
-const N: u8 = '\n' as u8;
-const RN: [u8; 2] = ['\r' as u8, '\n' as u8];
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for ParseError {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::UnexpectedEof => ParseError::Eof,
+            _ => ParseError::Io(err)
+        }
+    }
+}
+
+/// Bounds on the size of a request, so that a client can't make the server buffer as much
+/// as it likes. Line lengths include the line ending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// The longest Request-Line accepted.
+    pub max_request_line: usize,
+    /// The most header fields accepted, and separately the most trailer fields.
+    pub max_headers: usize,
+    /// The most bytes accepted across all header lines, and separately across all trailer lines.
+    pub max_header_bytes: usize,
+    /// The longest body accepted, after any chunked coding is removed.
+    pub max_body_size: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_request_line: 8 * 1024,
+            max_headers: 100,
+            max_header_bytes: 64 * 1024,
+            max_body_size: 8 * 1024 * 1024,
+        }
+    }
+}
 
 /// Contains methods for parsing an HTTP request from types implementing the Read trait.
 pub struct HttpParser<T: BufRead> {
-    reader: T
+    reader: T,
+    limits: ParseLimits,
 }
 
 impl<T: BufRead> HttpParser<T> {
-    /// Gets a new HttpParser.
-    pub fn new(reader: T) -> Self { HttpParser{ reader } }
+    /// Gets a new HttpParser, with the default limits.
+    pub fn new(reader: T) -> Self { Self::with_limits(reader, ParseLimits::default()) }
+
+    /// Gets a new HttpParser which rejects requests larger than limits.
+    pub fn with_limits(reader: T, limits: ParseLimits) -> Self { HttpParser{ reader, limits } }
+
+    pub fn parse_http_request(&mut self) -> Result<HttpRequest, ParseError> {
+        let mut request = self.parse_http_head()?;
+        self.parse_http_body(&mut request)?;
 
-    pub fn parse_http_request(&mut self) -> HttpRequest {
-        let mut request = self.parse_request_head();
-        if request::BODIED_METHODS.contains(&request.method) {
-            self.parse_request_body(&mut request).unwrap();
+        Ok(request)
+    }
+
+    /// Reads the body of a request whose head was read by `parse_http_head`, if it has one.
+    pub fn parse_http_body(&mut self, request: &mut HttpRequest) -> Result<(), ParseError> {
+        let has_body = request.http_version != HttpVersion::Http09 && (
+            request.headers.contains_key("transfer-encoding")
+                || request.headers.contains_key("content-length")
+                || request::BODIED_METHODS.contains(&request.method)
+        );
+        if has_body {
+            self.parse_request_body(request)?;
         }
+        Ok(())
+    }
+
+    /// private: Read a single line, including the trailing `\n`, onto the end of vec.
+    /// Fails with `ParseError::Eof` if the reader is exhausted before a `\n` is found, and
+    /// with too_long once more than limit bytes have been read without finding one.
+    fn read_line(&mut self, vec: &mut Vec<u8>, limit: usize, too_long: ParseError) -> Result<(), ParseError> {
+        let start = vec.len();
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(available) => available,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into())
+            };
+            if available.is_empty() {
+                return Err(ParseError::Eof);
+            }
+
+            let (found, used) = match available.iter().position(|&b| b == N) {
+                Some(i) => (true, i + 1),
+                None => (false, available.len())
+            };
+            if vec.len() - start + used > limit {
+                return Err(too_long);
+            }
+            vec.extend_from_slice(&available[..used]);
+            self.reader.consume(used);
 
-        request
+            if found {
+                return Ok(());
+            }
+        }
     }
 
-    /// private: Read and parse the head of an HTTP request from a BufReader.
-    fn parse_request_head(&mut self) -> HttpRequest {
+    /// Reads the Request-Line and header fields of a request, leaving its body unread.
+    pub fn parse_http_head(&mut self) -> Result<HttpRequest, ParseError> {
         let mut vec: Vec<u8> = vec![];
 
         // Parse the Request-Line
-        self.reader.read_until(N, &mut vec).unwrap();
+        self.read_line(&mut vec, self.limits.max_request_line, ParseError::RequestLineTooLong)?;
         let string = String::from_utf8_lossy(&vec);
-        let mut split = string.split(" ");
-        let method: HttpMethod = split.next().unwrap().trim().try_into().unwrap();
-        let mut uri = split.next().unwrap().trim().to_string();
-        if !uri.starts_with("/") {
-            uri = format!("/{}", uri);
+        let line = string.trim_end();
+        let mut split = line.split(' ');
+        let (method, uri, http_version) = match (split.next(), split.next(), split.next(), split.next()) {
+            (Some(method), Some(uri), Some(version), None) if !method.is_empty() && !uri.is_empty() => {
+                (method, uri, Some(version))
+            },
+            // an HTTP/0.9 simple request
+            (Some("GET"), Some(uri), None, None) if !uri.is_empty() => ("GET", uri, None),
+            _ => return Err(ParseError::BadRequestLine(line.to_string()))
+        };
+
+        let method: HttpMethod = method.try_into()
+            .map_err(|_| ParseError::UnknownMethod(method.to_string()))?;
+        let uri: RequestTarget = uri.try_into()
+            .map_err(|_| ParseError::BadRequestLine(line.to_string()))?;
+        // the authority-form is only for CONNECT, and the asterisk-form only for OPTIONS
+        let valid_form = match (&method, &uri) {
+            (HttpMethod::CONNECT, RequestTarget::Authority(_)) => true,
+            (HttpMethod::CONNECT, _) | (_, RequestTarget::Authority(_)) => false,
+            (HttpMethod::OPTIONS, RequestTarget::Asterisk) => true,
+            (_, RequestTarget::Asterisk) => false,
+            _ => true
+        };
+        if !valid_form {
+            return Err(ParseError::BadRequestLine(line.to_string()));
         }
-        let uri = PathBuf::from(uri);
-        let http_version = split.next().unwrap().trim().to_string();
+        let (http_version, headers) = match http_version {
+            Some(http_version) => (Self::parse_http_version(http_version, line)?, self.parse_header_fields()?),
+            None => (HttpVersion::Http09, HttpHeaders::new())
+        };
 
-        vec.clear();
+        Ok(HttpRequest {
+            method,
+            uri,
+            http_version,
+            headers,
+            body: None,
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
+        })
+    }
+
+    /// private: Read `name: value` lines until an empty line is found. Used for both the
+    /// request headers and the trailer section of a chunked body.
+    fn parse_header_fields(&mut self) -> Result<HttpHeaders, ParseError> {
+        let mut vec: Vec<u8> = vec![];
 
-        // read bytes, with \n delimiter, until we find \r\n\r\n
-        let mut headers: HashMap<String, String> = HashMap::new();
+        // read bytes, with \n delimiter, until we find an empty line
+        let mut headers = HttpHeaders::new();
+        let mut remaining = self.limits.max_header_bytes;
         loop {
-            self.reader.read_until(N, &mut vec).unwrap();
+            self.read_line(&mut vec, remaining, ParseError::HeadersTooLarge)?;
+            remaining -= vec.len();
 
-            if vec.starts_with(&RN) { break; }
+            if vec.starts_with(&RN) || vec.starts_with(&[N]) { break; }
+            if headers.len() >= self.limits.max_headers {
+                return Err(ParseError::HeadersTooLarge);
+            }
 
-            // split into two, by first `:`
+            // split into two, by first `:`. The name is a token with nothing around it, which
+            // also rules out obs-fold continuation lines, as they start with whitespace
             let string = String::from_utf8_lossy(&vec);
-            let mut splitter = string.split(':');
+            let (key, value) = match string.split_once(':') {
+                Some((key, value)) if !key.is_empty() && key.chars().all(request::is_tchar) => (key, value),
+                _ => return Err(ParseError::MalformedHeader(string.trim_end().to_string()))
+            };
 
-            let key = splitter.next().unwrap().trim().to_lowercase();
-            let value = splitter.fold("".to_string(), |a, b| a + b).trim().to_string();
-
-            headers.insert(key.into(), value.into());
+            headers.append(key, value.trim());
 
             vec.clear()
         }
 
-        HttpRequest {
-            method,
-            uri,
-            http_version,
-            headers,
-            body: None
+        Ok(headers)
+    }
+
+    /// private: Parse an HTTP-Version of the form `HTTP/<major>.<minor>`.
+    /// Only major version 1 is supported.
+    fn parse_http_version(version: &str, line: &str) -> Result<HttpVersion, ParseError> {
+        if !HttpVersion::is_well_formed(version) {
+            return Err(ParseError::BadRequestLine(line.to_string()));
+        }
+
+        match version.try_into() {
+            Ok(HttpVersion::Http10) => Ok(HttpVersion::Http10),
+            Ok(HttpVersion::Http11) => Ok(HttpVersion::Http11),
+            _ => Err(ParseError::BadVersion(version.to_string()))
         }
     }
 
     /// private: Read and parse an HTTP body from the BufReader and attach it to request.
-    /// The request must already have headers, so that we can grab the content-length.
-    fn parse_request_body(&mut self, request: &mut HttpRequest) -> std::io::Result<()> {
-        let content_length: usize = request.headers.get("content-length").unwrap().parse().unwrap();
+    /// The request must already have headers, so that we can grab the content-length
+    /// or transfer-encoding.
+    fn parse_request_body(&mut self, request: &mut HttpRequest) -> Result<(), ParseError> {
+        if let Some(transfer_encoding) = request.headers.get_combined("transfer-encoding") {
+            if request.headers.contains_key("content-length") {
+                return Err(ParseError::ConflictingLength);
+            }
+            // chunked must be the final coding, and we don't implement any others
+            if !transfer_encoding.eq_ignore_ascii_case("chunked") {
+                return Err(ParseError::UnsupportedTransferEncoding(transfer_encoding));
+            }
+            return self.parse_chunked_body(request);
+        }
+
+        // repeated lengths are allowed, as long as they all agree
+        let content_length = request.headers.get_combined("content-length")
+            .ok_or(ParseError::MissingContentLength)?;
+        let mut lengths = content_length.split(',').map(|length| Self::parse_content_length(length.trim()));
+        let content_length: usize = match lengths.next() {
+            Some(Some(length)) if lengths.all(|other| other == Some(length)) => length,
+            _ => return Err(ParseError::InvalidContentLength(content_length))
+        };
+        // check before allocating, the length is whatever the client claims it is
+        if content_length > self.limits.max_body_size {
+            return Err(ParseError::BodyTooLarge);
+        }
 
         let mut data: Vec<u8> = vec![0u8; content_length];
         self.reader.read_exact(&mut data)?;
@@ -84,6 +313,60 @@ impl<T: BufRead> HttpParser<T> {
         request.body = Some(data);
         Ok(())
     }
+
+    /// private: Parse a Content-Length, which is only ever digits. `str::parse` would also
+    /// take a sign, and a length read differently from another server invites smuggling.
+    fn parse_content_length(length: &str) -> Option<usize> {
+        if length.is_empty() || !length.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        length.parse().ok()
+    }
+
+    /// private: Decode a `Transfer-Encoding: chunked` body, attaching the data and any
+    /// trailer fields to request. Chunk extensions are ignored.
+    fn parse_chunked_body(&mut self, request: &mut HttpRequest) -> Result<(), ParseError> {
+        let mut data: Vec<u8> = vec![];
+        let mut vec: Vec<u8> = vec![];
+
+        loop {
+            // chunk-size [ ; chunk-ext ] CRLF
+            vec.clear();
+            self.read_line(&mut vec, self.limits.max_request_line, ParseError::InvalidChunk("chunk-size line too long".to_string()))?;
+            let line = String::from_utf8_lossy(&vec);
+            let line = line.trim_end_matches(['\r', '\n']);
+            // whitespace is only allowed before a chunk-ext, and `from_str_radix` would take a sign
+            let size = match line.split_once(';') {
+                Some((size, _)) => size.trim_end_matches([' ', '\t']),
+                None => line
+            };
+            let size = Some(size)
+                .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|size| usize::from_str_radix(size, 16).ok())
+                .ok_or_else(|| ParseError::InvalidChunk(line.to_string()))?;
+
+            // last-chunk
+            if size == 0 { break; }
+
+            // chunk-data CRLF
+            if size > self.limits.max_body_size - data.len() {
+                return Err(ParseError::BodyTooLarge);
+            }
+            let start = data.len();
+            data.resize(start + size, 0);
+            self.reader.read_exact(&mut data[start..])?;
+
+            vec.clear();
+            self.read_line(&mut vec, RN.len(), ParseError::InvalidChunk("missing CRLF after chunk-data".to_string()))?;
+            if vec != RN && vec != [N] {
+                return Err(ParseError::InvalidChunk(String::from_utf8_lossy(&vec).trim_end().to_string()));
+            }
+        }
+
+        request.trailers = self.parse_header_fields()?;
+        request.body = Some(data);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -92,22 +375,174 @@ mod tests {
 
     const SIMPLE_REQUEST_STR: &str = "GET / HTTP/1.1\r\nAccept: */*\r\n\r\n";
     fn get_simple_request() -> HttpRequest {
-        let mut ex_headers = HashMap::new();
-        ex_headers.insert("accept".to_string(), "*/*".to_string());
+        let mut ex_headers = HttpHeaders::new();
+        ex_headers.insert("Accept", "*/*");
         HttpRequest {
             method: HttpMethod::GET,
-            uri: PathBuf::from("/"),
-            http_version: "HTTP/1.1".to_string(),
+            uri: "/".try_into().unwrap(),
+            http_version: HttpVersion::Http11,
             headers: ex_headers,
-            body: None
+            body: None,
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
         }
     }
 
+    fn parse(request_str: &str) -> Result<HttpRequest, ParseError> {
+        HttpParser::new(request_str.as_bytes()).parse_http_request()
+    }
+
     #[test]
     fn parse_simple_request() {
         let mut parser: HttpParser<&[u8]> = HttpParser::new(SIMPLE_REQUEST_STR.as_bytes());
-        let request = parser.parse_http_request();
+        let request = parser.parse_http_request().unwrap();
 
         assert_eq!(request, get_simple_request());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(parse("GET / HTTP/1.0\r\n\r\n").unwrap().http_version, HttpVersion::Http10);
+        assert_eq!(parse("GET / HTTP/1.9\r\n\r\n").unwrap().http_version, HttpVersion::Http11);
+
+        let request = parse("GET /index.html\r\n").unwrap();
+        assert_eq!(request.http_version, HttpVersion::Http09);
+        assert_eq!(request.uri.path(), "/index.html");
+    }
+
+    #[test]
+    fn parse_request_targets() {
+        let request = parse("GET /search?q=http HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.uri.path(), "/search");
+        assert_eq!(request.uri.query_params().get("q"), Some("http"));
+
+        let request = parse("CONNECT example.com:443 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.uri, RequestTarget::Authority("example.com:443".to_string()));
+
+        let request = parse("OPTIONS * HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.uri, RequestTarget::Asterisk);
+
+        assert!(matches!(parse("GET * HTTP/1.1\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+        assert!(matches!(parse("GET example.com:443 HTTP/1.1\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+        assert!(matches!(parse("CONNECT / HTTP/1.1\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+    }
+
+    #[test]
+    fn parse_extension_method() {
+        let request = parse("PROPFIND /file HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.method, HttpMethod::Extension("PROPFIND".to_string()));
+    }
+
+    #[test]
+    fn parse_repeated_headers() {
+        let request = parse(concat!(
+            "POST / HTTP/1.1\r\n",
+            "Via: 1.0 a\r\n",
+            "Content-Length: 4\r\n",
+            "via: 1.1 b\r\n",
+            "Content-Length: 4\r\n",
+            "\r\nbody"
+        )).unwrap();
+        assert_eq!(request.headers.get_all("Via"), vec!["1.0 a", "1.1 b"]);
+        assert_eq!(request.body, Some(b"body".to_vec()));
+
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 4\r\nContent-Length: 5\r\n\r\nbody"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+    }
+
+    #[test]
+    fn parse_header_value_with_colon() {
+        let request = parse("GET / HTTP/1.1\r\nHost: localhost:8080\r\n\r\n").unwrap();
+        assert_eq!(request.headers.get("host"), Some(&"localhost:8080".to_string()));
+    }
+
+    #[test]
+    fn parse_chunked_request() {
+        let request = parse(concat!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "7 ;name=value\r\nHello, \r\n",
+            "6\r\nWorld!\r\n",
+            "0\r\n",
+            "Expires: never\r\n",
+            "\r\n"
+        )).unwrap();
+
+        assert_eq!(request.body, Some(b"Hello, World!".to_vec()));
+        assert_eq!(request.trailers.get("expires"), Some(&"never".to_string()));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(parse("POST /\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+        assert!(matches!(parse("GET / HTTP/1.1 extra\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+        assert!(matches!(parse("GET / HTTX/1.1\r\n\r\n"), Err(ParseError::BadRequestLine(_))));
+        assert!(matches!(parse("FR{OB / HTTP/1.1\r\n\r\n"), Err(ParseError::UnknownMethod(_))));
+        assert!(matches!(parse("GET / HTTP/2.0\r\n\r\n"), Err(ParseError::BadVersion(_))));
+        assert!(matches!(parse("GET / HTTP/3.0\r\n\r\n"), Err(ParseError::BadVersion(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nAccept\r\n\r\n"), Err(ParseError::MalformedHeader(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nHost : x\r\n\r\n"), Err(ParseError::MalformedHeader(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nAccept: text/html,\r\n  text/plain\r\n\r\n"), Err(ParseError::MalformedHeader(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\n Host: x\r\n\r\n"), Err(ParseError::MalformedHeader(_))));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nX(Y): x\r\n\r\n"), Err(ParseError::MalformedHeader(_))));
+        assert!(matches!(parse("POST / HTTP/1.1\r\n\r\n"), Err(ParseError::MissingContentLength)));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: ten\r\n\r\n"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+        for length in ["+5", "-5", "5 5", "0x5", "99999999999999999999999"] {
+            let request_str = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nhello", length);
+            assert!(matches!(parse(&request_str), Err(ParseError::InvalidContentLength(_))));
+        }
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n"),
+            Err(ParseError::ConflictingLength)
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n"),
+            Err(ParseError::UnsupportedTransferEncoding(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            Err(ParseError::InvalidChunk(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcd\r\n0\r\n\r\n"),
+            Err(ParseError::InvalidChunk(_))
+        ));
+        for size in ["+5", " 5", "5 ", "", "-0"] {
+            let request_str = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{}\r\nhello\r\n0\r\n\r\n", size);
+            assert!(matches!(parse(&request_str), Err(ParseError::InvalidChunk(_))));
+        }
+        assert!(matches!(parse(""), Err(ParseError::Eof)));
+        assert!(matches!(parse("GET / HTTP/1.1\r\nAccept: */*\r\n"), Err(ParseError::Eof)));
+        assert!(matches!(parse("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"), Err(ParseError::Eof)));
+    }
+
+    #[test]
+    fn parse_limits() {
+        let limits = ParseLimits { max_request_line: 20, max_headers: 2, max_header_bytes: 32, max_body_size: 8 };
+        let parse = |request_str: &str| HttpParser::with_limits(request_str.as_bytes(), limits).parse_http_request();
+
+        assert!(parse("GET /0123 HTTP/1.1\r\n\r\n").is_ok());
+        assert!(matches!(parse("GET /012345 HTTP/1.1\r\n\r\n"), Err(ParseError::RequestLineTooLong)));
+
+        assert!(parse("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n").is_ok());
+        assert!(matches!(parse("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"), Err(ParseError::HeadersTooLarge)));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nAccept: text/html, text/plain, */*\r\n\r\n"),
+            Err(ParseError::HeadersTooLarge)
+        ));
+
+        assert!(parse("POST / HTTP/1.1\r\nContent-Length: 8\r\n\r\n01234567").is_ok());
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n"),
+            Err(ParseError::BodyTooLarge)
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\n01234\r\n5\r\n56789\r\n0\r\n\r\n"),
+            Err(ParseError::BodyTooLarge)
+        ));
+    }
+}
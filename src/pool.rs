@@ -0,0 +1,129 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+
+/// A fixed number of worker threads, each handling jobs of type `T` from a bounded queue.
+/// Dropping the pool lets the workers finish the queued jobs and then joins them.
+pub struct WorkerPool<T: Send + 'static> {
+    sender: Option<SyncSender<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> WorkerPool<T> {
+    /// Starts size workers which run handler on each job. At most queue_size jobs wait for
+    /// a free worker. A handler which panics fails only the job it was handling.
+    pub fn new<F>(size: usize, queue_size: usize, handler: F) -> Self
+        where F: Fn(T) + Send + Sync + 'static
+    {
+        assert!(size > 0, "a worker pool needs at least one worker");
+
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let receiver: Arc<Mutex<Receiver<T>>> = Arc::new(Mutex::new(receiver));
+        let handler = Arc::new(handler);
+
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = receiver.clone();
+                let handler = handler.clone();
+                thread::spawn(move || loop {
+                    // the lock is only held while waiting for a job, not while handling it
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return
+                    };
+                    match job {
+                        Ok(job) => { let _ = panic::catch_unwind(AssertUnwindSafe(|| handler(job))); },
+                        Err(_) => return
+                    }
+                })
+            })
+            .collect();
+
+        Self { sender: Some(sender), workers }
+    }
+
+    /// Queues job, waiting for room in the queue if it is full.
+    pub fn execute(&self, job: T) {
+        if let Some(sender) = &self.sender {
+            // the workers only hang up when the pool is dropped
+            let _ = sender.send(job);
+        }
+    }
+
+    /// Queues job if there is room in the queue, otherwise hands it back.
+    pub fn try_execute(&self, job: T) -> Result<(), T> {
+        match &self.sender {
+            Some(sender) => sender.try_send(job).map_err(|err| match err {
+                TrySendError::Full(job) | TrySendError::Disconnected(job) => job
+            }),
+            None => Err(job)
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl<T: Send + 'static> Drop for WorkerPool<T> {
+    fn drop(&mut self) {
+        // hanging up makes each worker return once the queue is empty
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn runs_every_job() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let pool = WorkerPool::new(4, 2, move |n: usize| { counter.fetch_add(n, Ordering::SeqCst); });
+
+        for n in 1..=10 {
+            pool.execute(n);
+        }
+        drop(pool);
+
+        assert_eq!(count.load(Ordering::SeqCst), 55);
+    }
+
+    #[test]
+    fn rejects_when_full() {
+        let (release, blocked) = channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let pool = WorkerPool::new(1, 1, move |_: usize| { blocked.lock().unwrap().recv().unwrap(); });
+
+        // the second job waits until the worker has taken the first, then fills the queue
+        pool.execute(1);
+        pool.execute(2);
+        assert_eq!(pool.try_execute(3), Err(3));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+    }
+
+    #[test]
+    fn survives_panics() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let pool = WorkerPool::new(1, 4, move |n: usize| {
+            if n == 0 { panic!("job failed"); }
+            counter.fetch_add(n, Ordering::SeqCst);
+        });
+
+        pool.execute(0);
+        pool.execute(1);
+        drop(pool);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
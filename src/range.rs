@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::conditional::EntityTag;
+use crate::date::parse_http_date;
+use crate::request::{HttpRequest, HttpResponse, HttpMethod, HttpStatusCode, HttpHeaders, HttpBody};
+
+/// More ranges than this in one request are ignored, and the whole representation sent.
+const MAX_RANGES: usize = 64;
+
+/// One range-spec of a `Range: bytes=` header, before it is resolved against a length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, or `first-` to the end when last is `None`. Both are inclusive.
+    FromTo(u64, Option<u64>),
+    /// `-suffix`, the last suffix bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Parses the value of a `Range` header. Returns `None` if the unit isn't `bytes` or any
+    /// range-spec is malformed, in which case the header should be ignored.
+    pub fn parse_header(value: &str) -> Option<Vec<ByteRange>> {
+        let (unit, specs) = value.split_once('=')?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+
+        let ranges = specs.split(',')
+            .map(|spec| spec.trim())
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| ByteRange::try_from(spec).ok())
+            .collect::<Option<Vec<ByteRange>>>()?;
+        if ranges.is_empty() {
+            return None;
+        }
+        Some(ranges)
+    }
+
+    /// The inclusive `(first, last)` byte positions this range covers in a representation of
+    /// length bytes, or `None` if it is unsatisfiable.
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::FromTo(first, _) if first >= length => None,
+            ByteRange::FromTo(first, last) => Some((first, last.unwrap_or(u64::MAX).min(length - 1))),
+            ByteRange::Suffix(suffix) if suffix == 0 || length == 0 => None,
+            ByteRange::Suffix(suffix) => Some((length - suffix.min(length), length - 1)),
+        }
+    }
+}
+
+impl TryFrom<&str> for ByteRange {
+    type Error = String;
+
+    fn try_from(spec: &str) -> Result<Self, Self::Error> {
+        let position = |p: &str| match !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) {
+            true => p.parse::<u64>().ok(),
+            false => None
+        };
+
+        let range = match spec.split_once('-') {
+            Some(("", suffix)) => position(suffix).map(ByteRange::Suffix),
+            Some((first, "")) => position(first).map(|first| ByteRange::FromTo(first, None)),
+            Some((first, last)) => match (position(first), position(last)) {
+                (Some(first), Some(last)) if first <= last => Some(ByteRange::FromTo(first, Some(last))),
+                _ => None
+            },
+            None => None
+        };
+        range.ok_or(format!("invalid range: {}", spec))
+    }
+}
+
+/// private: A piece of a partial response body, in the order it is sent.
+enum Part {
+    /// Bytes of the multipart framing.
+    Bytes(Vec<u8>),
+    /// The bytes of the representation from start, up to but not including end.
+    Range(u64, u64),
+}
+
+/// private: Reads the parts of a partial response out of a reader of the whole representation.
+/// Parts must be in ascending order, as the reader is only ever read forwards.
+struct RangeReader {
+    inner: Box<dyn Read + Send>,
+    position: u64,
+    parts: VecDeque<Part>,
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let RangeReader { inner, position, parts } = self;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            match parts.front_mut() {
+                None => return Ok(0),
+                Some(Part::Bytes(bytes)) if bytes.is_empty() => { parts.pop_front(); },
+                Some(Part::Bytes(bytes)) => {
+                    let size = bytes.len().min(buf.len());
+                    buf[..size].copy_from_slice(&bytes[..size]);
+                    bytes.drain(..size);
+                    return Ok(size);
+                },
+                Some(Part::Range(_, end)) if *position >= *end => { parts.pop_front(); },
+                Some(Part::Range(start, end)) => {
+                    // skip ahead to the start of the range
+                    if *position < *start {
+                        let skipped = std::io::copy(&mut inner.take(*start - *position), &mut std::io::sink())?;
+                        *position += skipped;
+                        if *position < *start {
+                            return Err(std::io::ErrorKind::UnexpectedEof.into());
+                        }
+                    }
+
+                    let size = (*end - *position).min(buf.len() as u64) as usize;
+                    let read = inner.read(&mut buf[..size])?;
+                    if read == 0 {
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *position += read as u64;
+                    return Ok(read);
+                },
+            }
+        }
+    }
+}
+
+impl HttpResponse {
+    /// Answers the `Range` header of a GET request from this response, when it is a 200 with a
+    /// body of known length. A satisfiable range gets a `206 Partial Content`, with a
+    /// `multipart/byteranges` body when there are several, and an unsatisfiable one a
+    /// `416 Range Not Satisfiable`. Ranges are sent in ascending order, with any that overlap
+    /// or touch merged.
+    ///
+    /// The range is ignored if an `If-Range` doesn't match the response's `ETag` or
+    /// `Last-Modified`. Responses the range applies to advertise `Accept-Ranges: bytes`.
+    pub fn apply_range(mut self, request: &HttpRequest) -> HttpResponse {
+        let length = match (self.status_code, self.get_body(), self.get_body().content_length()) {
+            (_, HttpBody::Empty, _) => return self,
+            (HttpStatusCode::OK, _, Some(length)) => length,
+            _ => return self
+        };
+        self.headers.insert("accept-ranges", "bytes");
+
+        let range = match request.headers.get_combined("range") {
+            Some(range) if request.method == HttpMethod::GET => range,
+            _ => return self
+        };
+        if let Some(if_range) = request.headers.get("if-range") {
+            if !self.if_range_matches(if_range) {
+                return self;
+            }
+        }
+        let ranges = match ByteRange::parse_header(&range) {
+            Some(ranges) if ranges.len() <= MAX_RANGES => ranges,
+            _ => return self
+        };
+
+        let mut resolved: Vec<(u64, u64)> = ranges.iter().filter_map(|r| r.resolve(length)).collect();
+        if resolved.is_empty() {
+            return Self::range_not_satisfiable(length);
+        }
+        resolved.sort_unstable();
+        let mut merged: Vec<(u64, u64)> = vec![];
+        for (first, last) in resolved {
+            match merged.last_mut() {
+                Some(previous) if first <= previous.1.saturating_add(1) => previous.1 = previous.1.max(last),
+                _ => merged.push((first, last))
+            }
+        }
+
+        let inner: Box<dyn Read + Send> = match self.take_body() {
+            HttpBody::Bytes(data) => Box::new(Cursor::new(data)),
+            HttpBody::Reader { reader, .. } => reader,
+            _ => unreachable!("only bodies of known length have ranges applied")
+        };
+        let mut parts = VecDeque::new();
+        if let [(first, last)] = merged[..] {
+            self.headers.insert("content-range", &format!("bytes {}-{}/{}", first, last, length));
+            parts.push_back(Part::Range(first, last + 1));
+        } else {
+            let boundary = Self::boundary();
+            let content_type = self.headers.unset("content-type");
+            for (i, (first, last)) in merged.into_iter().enumerate() {
+                let mut head = if i == 0 { String::new() } else { "\r\n".to_string() };
+                head.push_str(&format!("--{}\r\n", boundary));
+                if let Some(content_type) = &content_type {
+                    head.push_str(&format!("Content-Type: {}\r\n", content_type));
+                }
+                head.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n\r\n", first, last, length));
+                parts.push_back(Part::Bytes(head.into_bytes()));
+                parts.push_back(Part::Range(first, last + 1));
+            }
+            parts.push_back(Part::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+            self.headers.insert("content-type", &format!("multipart/byteranges; boundary={}", boundary));
+        }
+
+        let body_length = parts.iter().map(|part| match part {
+            Part::Bytes(bytes) => bytes.len() as u64,
+            Part::Range(start, end) => end - start,
+        }).sum();
+        self.status_code = HttpStatusCode::PARTIAL_CONTENT;
+        self.reason_phrase = None;
+        self.set_body(HttpBody::Reader {
+            reader: Box::new(RangeReader { inner, position: 0, parts }),
+            length: Some(body_length)
+        });
+        self
+    }
+
+    /// private: Whether the representation is the one named by an `If-Range`, either by a
+    /// strong entity-tag or by its exact modification date.
+    fn if_range_matches(&self, if_range: &str) -> bool {
+        let if_range = if_range.trim();
+        if if_range.starts_with('"') || if_range.starts_with("W/") {
+            let etag = self.headers.get("etag").and_then(|etag| EntityTag::try_from(etag.as_str()).ok());
+            return match (etag, EntityTag::try_from(if_range)) {
+                (Some(etag), Ok(if_range)) => etag.strong_eq(&if_range),
+                _ => false
+            };
+        }
+
+        let last_modified = self.headers.get("last-modified").and_then(|date| parse_http_date(date));
+        match (last_modified, parse_http_date(if_range)) {
+            (Some(last_modified), Some(date)) => last_modified == date,
+            _ => false
+        }
+    }
+
+    /// private: A boundary for a multipart body, unlikely to appear in the body itself.
+    fn boundary() -> String {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        format!("byteranges-{:x}-{:x}", nanos, COUNT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn range_not_satisfiable(length: u64) -> HttpResponse {
+        let mut headers = HttpHeaders::new();
+        headers.insert("content-range", &format!("bytes */{}", length));
+        HttpResponse::error(HttpStatusCode::RANGE_NOT_SATISFIABLE, headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{request, ok, body};
+
+    /// A 200 for the 26 letters of the alphabet, tagged "v1".
+    fn response() -> HttpResponse {
+        let body = HttpBody::Reader { reader: Box::new(Cursor::new(b"abcdefghijklmnopqrstuvwxyz".to_vec())), length: Some(26) };
+        let mut response = ok(&[("content-type", "text/plain")], body);
+        response.set_etag(&EntityTag::strong("v1"));
+        response
+    }
+
+    fn ranged(range: &str) -> HttpResponse {
+        response().apply_range(&request(&format!("GET / HTTP/1.1\r\nRange: {}\r\n\r\n", range)))
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(
+            ByteRange::parse_header("bytes=0-4, 10-, -3"),
+            Some(vec![ByteRange::FromTo(0, Some(4)), ByteRange::FromTo(10, None), ByteRange::Suffix(3)])
+        );
+        assert_eq!(ByteRange::parse_header("Bytes=5-5"), Some(vec![ByteRange::FromTo(5, Some(5))]));
+        assert_eq!(ByteRange::parse_header("items=0-4"), None);
+        assert_eq!(ByteRange::parse_header("bytes=4-0"), None);
+        assert_eq!(ByteRange::parse_header("bytes=+1-2"), None);
+        assert_eq!(ByteRange::parse_header("bytes="), None);
+
+        assert_eq!(ByteRange::FromTo(20, Some(100)).resolve(26), Some((20, 25)));
+        assert_eq!(ByteRange::FromTo(26, None).resolve(26), None);
+        assert_eq!(ByteRange::Suffix(100).resolve(26), Some((0, 25)));
+        assert_eq!(ByteRange::Suffix(0).resolve(26), None);
+    }
+
+    #[test]
+    fn single_range() {
+        let response = ranged("bytes=2-4");
+        assert_eq!(response.status_code, HttpStatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers.get("content-range"), Some(&"bytes 2-4/26".to_string()));
+        assert_eq!(response.headers.get("content-length"), Some(&"3".to_string()));
+        assert_eq!(response.headers.get("accept-ranges"), Some(&"bytes".to_string()));
+        assert_eq!(body(response), "cde");
+
+        assert_eq!(body(ranged("bytes=-3")), "xyz");
+        assert_eq!(body(ranged("bytes=24-")), "yz");
+        // overlapping ranges are merged
+        assert_eq!(body(ranged("bytes=0-2,1-4")), "abcde");
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        let response = ranged("bytes=-2,0-1");
+        assert_eq!(response.status_code, HttpStatusCode::PARTIAL_CONTENT);
+        let content_type = response.headers.get("content-type").unwrap().clone();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap().to_string();
+        let length: usize = response.headers.get("content-length").unwrap().parse().unwrap();
+
+        let body = body(response);
+        assert_eq!(body.len(), length);
+        assert_eq!(body, format!(concat!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/26\r\n\r\nab",
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 24-25/26\r\n\r\nyz",
+            "\r\n--{b}--\r\n"
+        ), b = boundary));
+    }
+
+    #[test]
+    fn unsatisfiable_range() {
+        let response = ranged("bytes=26-");
+        assert_eq!(response.status_code, HttpStatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers.get("content-range"), Some(&"bytes */26".to_string()));
+
+        // a satisfiable range among unsatisfiable ones is still served
+        assert_eq!(body(ranged("bytes=30-40,0-0")), "a");
+    }
+
+    #[test]
+    fn ignored_ranges() {
+        assert_eq!(ranged("bytes=z-").status_code, HttpStatusCode::OK);
+        assert_eq!(ranged("lines=1-2").status_code, HttpStatusCode::OK);
+
+        let post = response().apply_range(&request("POST / HTTP/1.1\r\nContent-Length: 0\r\nRange: bytes=0-1\r\n\r\n"));
+        assert_eq!(post.status_code, HttpStatusCode::OK);
+
+        let mut not_found = response();
+        not_found.status_code = HttpStatusCode::NOT_FOUND;
+        let not_found = not_found.apply_range(&request("GET / HTTP/1.1\r\nRange: bytes=0-1\r\n\r\n"));
+        assert_eq!(not_found.status_code, HttpStatusCode::NOT_FOUND);
+        assert!(!not_found.headers.contains_key("accept-ranges"));
+    }
+
+    #[test]
+    fn if_range() {
+        let range = |if_range: &str| {
+            response().apply_range(&request(&format!("GET / HTTP/1.1\r\nRange: bytes=0-0\r\nIf-Range: {}\r\n\r\n", if_range)))
+        };
+        assert_eq!(range("\"v1\"").status_code, HttpStatusCode::PARTIAL_CONTENT);
+        assert_eq!(range("\"v0\"").status_code, HttpStatusCode::OK);
+        assert_eq!(range("W/\"v1\"").status_code, HttpStatusCode::OK);
+        assert_eq!(range("Sun, 06 Nov 1994 08:49:37 GMT").status_code, HttpStatusCode::OK);
+    }
+}
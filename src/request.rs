@@ -5,6 +5,8 @@ pub use status::*;
 pub use request::*;
 pub use response::*;
 pub use headers::*;
+pub use body::*;
+pub use version::*;
 
 mod method {
     pub const BODIED_METHODS: [HttpMethod; 3] = [
@@ -13,9 +15,10 @@ mod method {
         HttpMethod::PATCH,
     ];
 
-    /// HTTP Methods as defined in at <https://www.w3.org/Protocols/rfc2616/rfc2616-sec9.html>
+    /// HTTP Methods as defined in at <https://www.w3.org/Protocols/rfc2616/rfc2616-sec9.html>,
+    /// plus PATCH. Any other method token, such as the WebDAV `PROPFIND`, is an `Extension`.
     #[allow(dead_code)]
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
     pub enum HttpMethod {
         OPTIONS,
         GET,
@@ -25,7 +28,8 @@ mod method {
         DELETE,
         TRACE,
         CONNECT,
-        PATCH
+        PATCH,
+        Extension(String)
     }
 
     impl HttpMethod {
@@ -35,22 +39,39 @@ mod method {
                 HttpMethod::GET => "GET",
                 HttpMethod::HEAD => "HEAD",
                 HttpMethod::POST => "POST",
-                HttpMethod::PUT => "POST",
-                HttpMethod::DELETE => "POST",
+                HttpMethod::PUT => "PUT",
+                HttpMethod::DELETE => "DELETE",
                 HttpMethod::TRACE => "TRACE",
                 HttpMethod::CONNECT => "CONNECT",
                 HttpMethod::PATCH => "PATCH",
+                HttpMethod::Extension(method) => method,
             }
         }
     }
 
+    /// Whether c is a `tchar`, as defined at <https://www.rfc-editor.org/rfc/rfc9110#name-tokens>.
+    pub(crate) fn is_tchar(c: char) -> bool {
+        c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+    }
+
     impl std::convert::TryFrom<&str> for HttpMethod {
         type Error = String;
 
+        /// Parses a method token. Methods are case-sensitive, so `get` is an extension method.
         fn try_from(value: &str) -> Result<Self, Self::Error> {
             match value {
+                "OPTIONS" => Ok(HttpMethod::OPTIONS),
                 "GET" => Ok(HttpMethod::GET),
+                "HEAD" => Ok(HttpMethod::HEAD),
                 "POST" => Ok(HttpMethod::POST),
+                "PUT" => Ok(HttpMethod::PUT),
+                "DELETE" => Ok(HttpMethod::DELETE),
+                "TRACE" => Ok(HttpMethod::TRACE),
+                "CONNECT" => Ok(HttpMethod::CONNECT),
+                "PATCH" => Ok(HttpMethod::PATCH),
+                _ if !value.is_empty() && value.chars().all(is_tchar) => {
+                    Ok(HttpMethod::Extension(value.to_string()))
+                },
                 _ => Err(format!("Invalid HttpMethod: {}", value))
             }
         }
@@ -58,93 +79,211 @@ mod method {
 }
 
 mod status {
+    /// An HTTP status code. Codes are three digits, in the range 100-999.
     #[allow(dead_code)]
-    #[derive(Debug, Clone, PartialEq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     pub struct HttpStatusCode(pub i32);
 
     #[allow(dead_code)]
     impl HttpStatusCode {
+        pub const CONTINUE: HttpStatusCode = HttpStatusCode(100);
+        pub const SWITCHING_PROTOCOLS: HttpStatusCode = HttpStatusCode(101);
+        pub const PROCESSING: HttpStatusCode = HttpStatusCode(102);
+        pub const EARLY_HINTS: HttpStatusCode = HttpStatusCode(103);
+        pub const OK: HttpStatusCode = HttpStatusCode(200);
+        pub const CREATED: HttpStatusCode = HttpStatusCode(201);
+        pub const ACCEPTED: HttpStatusCode = HttpStatusCode(202);
+        pub const NON_AUTHORITATIVE_INFORMATION: HttpStatusCode = HttpStatusCode(203);
+        pub const NO_CONTENT: HttpStatusCode = HttpStatusCode(204);
+        pub const RESET_CONTENT: HttpStatusCode = HttpStatusCode(205);
+        pub const PARTIAL_CONTENT: HttpStatusCode = HttpStatusCode(206);
+        pub const MULTI_STATUS: HttpStatusCode = HttpStatusCode(207);
+        pub const ALREADY_REPORTED: HttpStatusCode = HttpStatusCode(208);
+        pub const IM_USED: HttpStatusCode = HttpStatusCode(226);
+        pub const MULTIPLE_CHOICES: HttpStatusCode = HttpStatusCode(300);
+        pub const MOVED_PERMANENTLY: HttpStatusCode = HttpStatusCode(301);
+        pub const FOUND: HttpStatusCode = HttpStatusCode(302);
+        pub const SEE_OTHER: HttpStatusCode = HttpStatusCode(303);
+        pub const NOT_MODIFIED: HttpStatusCode = HttpStatusCode(304);
+        pub const USE_PROXY: HttpStatusCode = HttpStatusCode(305);
+        pub const TEMPORARY_REDIRECT: HttpStatusCode = HttpStatusCode(307);
+        pub const PERMANENT_REDIRECT: HttpStatusCode = HttpStatusCode(308);
+        pub const BAD_REQUEST: HttpStatusCode = HttpStatusCode(400);
+        pub const UNAUTHORIZED: HttpStatusCode = HttpStatusCode(401);
+        pub const PAYMENT_REQUIRED: HttpStatusCode = HttpStatusCode(402);
+        pub const FORBIDDEN: HttpStatusCode = HttpStatusCode(403);
+        pub const NOT_FOUND: HttpStatusCode = HttpStatusCode(404);
+        pub const METHOD_NOT_ALLOWED: HttpStatusCode = HttpStatusCode(405);
+        pub const NOT_ACCEPTABLE: HttpStatusCode = HttpStatusCode(406);
+        pub const PROXY_AUTHENTICATION_REQUIRED: HttpStatusCode = HttpStatusCode(407);
+        pub const REQUEST_TIMEOUT: HttpStatusCode = HttpStatusCode(408);
+        pub const CONFLICT: HttpStatusCode = HttpStatusCode(409);
+        pub const GONE: HttpStatusCode = HttpStatusCode(410);
+        pub const LENGTH_REQUIRED: HttpStatusCode = HttpStatusCode(411);
+        pub const PRECONDITION_FAILED: HttpStatusCode = HttpStatusCode(412);
+        pub const CONTENT_TOO_LARGE: HttpStatusCode = HttpStatusCode(413);
+        pub const URI_TOO_LONG: HttpStatusCode = HttpStatusCode(414);
+        pub const UNSUPPORTED_MEDIA_TYPE: HttpStatusCode = HttpStatusCode(415);
+        pub const RANGE_NOT_SATISFIABLE: HttpStatusCode = HttpStatusCode(416);
+        pub const EXPECTATION_FAILED: HttpStatusCode = HttpStatusCode(417);
+        pub const MISDIRECTED_REQUEST: HttpStatusCode = HttpStatusCode(421);
+        pub const UNPROCESSABLE_CONTENT: HttpStatusCode = HttpStatusCode(422);
+        pub const LOCKED: HttpStatusCode = HttpStatusCode(423);
+        pub const FAILED_DEPENDENCY: HttpStatusCode = HttpStatusCode(424);
+        pub const TOO_EARLY: HttpStatusCode = HttpStatusCode(425);
+        pub const UPGRADE_REQUIRED: HttpStatusCode = HttpStatusCode(426);
+        pub const PRECONDITION_REQUIRED: HttpStatusCode = HttpStatusCode(428);
+        pub const TOO_MANY_REQUESTS: HttpStatusCode = HttpStatusCode(429);
+        pub const REQUEST_HEADER_FIELDS_TOO_LARGE: HttpStatusCode = HttpStatusCode(431);
+        pub const UNAVAILABLE_FOR_LEGAL_REASONS: HttpStatusCode = HttpStatusCode(451);
+        pub const INTERNAL_SERVER_ERROR: HttpStatusCode = HttpStatusCode(500);
+        pub const NOT_IMPLEMENTED: HttpStatusCode = HttpStatusCode(501);
+        pub const BAD_GATEWAY: HttpStatusCode = HttpStatusCode(502);
+        pub const SERVICE_UNAVAILABLE: HttpStatusCode = HttpStatusCode(503);
+        pub const GATEWAY_TIMEOUT: HttpStatusCode = HttpStatusCode(504);
+        pub const HTTP_VERSION_NOT_SUPPORTED: HttpStatusCode = HttpStatusCode(505);
+        pub const VARIANT_ALSO_NEGOTIATES: HttpStatusCode = HttpStatusCode(506);
+        pub const INSUFFICIENT_STORAGE: HttpStatusCode = HttpStatusCode(507);
+        pub const LOOP_DETECTED: HttpStatusCode = HttpStatusCode(508);
+        pub const NOT_EXTENDED: HttpStatusCode = HttpStatusCode(510);
+        pub const NETWORK_AUTHENTICATION_REQUIRED: HttpStatusCode = HttpStatusCode(511);
+
+        /// The reason phrase registered for this code at
+        /// <https://www.iana.org/assignments/http-status-codes>, if any.
+        pub fn reason_phrase(&self) -> Option<&'static str> {
+            match self.0 {
+                100 => Some("Continue"),
+                101 => Some("Switching Protocols"),
+                102 => Some("Processing"),
+                103 => Some("Early Hints"),
+                200 => Some("OK"),
+                201 => Some("Created"),
+                202 => Some("Accepted"),
+                203 => Some("Non-Authoritative Information"),
+                204 => Some("No Content"),
+                205 => Some("Reset Content"),
+                206 => Some("Partial Content"),
+                207 => Some("Multi-Status"),
+                208 => Some("Already Reported"),
+                226 => Some("IM Used"),
+                300 => Some("Multiple Choices"),
+                301 => Some("Moved Permanently"),
+                302 => Some("Found"),
+                303 => Some("See Other"),
+                304 => Some("Not Modified"),
+                305 => Some("Use Proxy"),
+                307 => Some("Temporary Redirect"),
+                308 => Some("Permanent Redirect"),
+                400 => Some("Bad Request"),
+                401 => Some("Unauthorized"),
+                402 => Some("Payment Required"),
+                403 => Some("Forbidden"),
+                404 => Some("Not Found"),
+                405 => Some("Method Not Allowed"),
+                406 => Some("Not Acceptable"),
+                407 => Some("Proxy Authentication Required"),
+                408 => Some("Request Timeout"),
+                409 => Some("Conflict"),
+                410 => Some("Gone"),
+                411 => Some("Length Required"),
+                412 => Some("Precondition Failed"),
+                413 => Some("Content Too Large"),
+                414 => Some("URI Too Long"),
+                415 => Some("Unsupported Media Type"),
+                416 => Some("Range Not Satisfiable"),
+                417 => Some("Expectation Failed"),
+                421 => Some("Misdirected Request"),
+                422 => Some("Unprocessable Content"),
+                423 => Some("Locked"),
+                424 => Some("Failed Dependency"),
+                425 => Some("Too Early"),
+                426 => Some("Upgrade Required"),
+                428 => Some("Precondition Required"),
+                429 => Some("Too Many Requests"),
+                431 => Some("Request Header Fields Too Large"),
+                451 => Some("Unavailable For Legal Reasons"),
+                500 => Some("Internal Server Error"),
+                501 => Some("Not Implemented"),
+                502 => Some("Bad Gateway"),
+                503 => Some("Service Unavailable"),
+                504 => Some("Gateway Timeout"),
+                505 => Some("HTTP Version Not Supported"),
+                506 => Some("Variant Also Negotiates"),
+                507 => Some("Insufficient Storage"),
+                508 => Some("Loop Detected"),
+                510 => Some("Not Extended"),
+                511 => Some("Network Authentication Required"),
+                _ => None
+            }
+        }
+
+        /// The reason phrase for this code. Unregistered codes get a phrase describing their class.
         pub fn description(&self) -> &str {
+            if let Some(phrase) = self.reason_phrase() {
+                return phrase;
+            }
             match self.0 {
-                // TODO: more statuses
-                100 => "Continue",
-                101 => "Switching Protocols",
-                102 => "Processing",
-                200 => "OK",
-                201 => "Created",
-                202 => "Accepted",
-                203 => "Non-authoritative Information",
-                204 => "No Content",
-                205 => "Reset Content",
-                206 => "Partial Content",
-                207 => "Multi-Status",
-                208 => "Already Reported",
-                300 => "Multiple Choices",
-                301 => "Moved Permanently",
-                302 => "Found",
-                303 => "See Other",
-                304 => "Not Modified",
-                305 => "Use Proxy",
-                307 => "Temporary Redirect",
-                308 => "Permanent Redirect",
-                400 => "Bad Request",
-                401 => "Unauthorized",
-                402 => "Payment Required",
-                403 => "Forbidden",
-                404 => "Not Found",
-                405 => "Status Not Implemented",
-                406 => "Status Not Implemented",
-                407 => "Status Not Implemented",
-                408 => "Status Not Implemented",
-                409 => "Status Not Implemented",
-                410 => "Status Not Implemented",
-                411 => "Status Not Implemented",
-                412 => "Status Not Implemented",
-                413 => "Status Not Implemented",
-                414 => "Status Not Implemented",
-                415 => "Status Not Implemented",
-                416 => "Status Not Implemented",
-                417 => "Status Not Implemented",
-                418 => "Status Not Implemented",
-                419 => "Status Not Implemented",
-                420 => "Status Not Implemented",
-                421 => "Status Not Implemented",
-                422 => "Status Not Implemented",
-                423 => "Status Not Implemented",
-                424 => "Status Not Implemented",
-                425 => "Status Not Implemented",
-                426 => "Status Not Implemented",
-                427 => "Status Not Implemented",
-                428 => "Status Not Implemented",
-                429 => "Status Not Implemented",
-                430 => "Status Not Implemented",
-                431 => "Status Not Implemented",
-                444 => "Status Not Implemented",
-                451 => "Status Not Implemented",
-                499 => "Status Not Implemented",
-                500 => "Status Not Implemented",
-                501 => "Status Not Implemented",
-                502 => "Status Not Implemented",
-                503 => "Status Not Implemented",
-                504 => "Status Not Implemented",
-                505 => "Status Not Implemented",
-                506 => "Status Not Implemented",
-                507 => "Status Not Implemented",
-                508 => "Status Not Implemented",
-                509 => "Status Not Implemented",
-                510 => "Status Not Implemented",
-                511 => "Status Not Implemented",
-                599 => "Status Not Implemented",
-                _ => panic!("invalid http status")
+                100..=199 => "Informational",
+                200..=299 => "Success",
+                300..=399 => "Redirection",
+                400..=499 => "Client Error",
+                500..=599 => "Server Error",
+                _ => ""
+            }
+        }
+
+        /// Whether this is a three digit code, as required on the wire.
+        pub fn is_valid(&self) -> bool {
+            (100..=999).contains(&self.0)
+        }
+
+        /// 1xx
+        pub fn is_informational(&self) -> bool {
+            (100..=199).contains(&self.0)
+        }
+
+        /// 2xx
+        pub fn is_success(&self) -> bool {
+            (200..=299).contains(&self.0)
+        }
+
+        /// 3xx
+        pub fn is_redirection(&self) -> bool {
+            (300..=399).contains(&self.0)
+        }
+
+        /// 4xx
+        pub fn is_client_error(&self) -> bool {
+            (400..=499).contains(&self.0)
+        }
+
+        /// 5xx
+        pub fn is_server_error(&self) -> bool {
+            (500..=599).contains(&self.0)
+        }
+    }
+
+    impl std::convert::TryFrom<i32> for HttpStatusCode {
+        type Error = String;
+
+        fn try_from(value: i32) -> Result<Self, Self::Error> {
+            let code = HttpStatusCode(value);
+            if code.is_valid() {
+                Ok(code)
+            } else {
+                Err(format!("Invalid HttpStatusCode: {}", value))
             }
         }
     }
 }
 
+#[allow(clippy::module_inception)]
 mod request {
-    use super::HttpMethod;
+    use super::{HttpMethod, HttpVersion, HttpHeaders};
+    use super::body::{write_chunk, write_trailers};
+    use crate::uri::RequestTarget;
     use std::collections::HashMap;
-    use std::path::PathBuf;
+    use std::io::Write;
 
     /// An HTTP request struct. Most operations on this struct are read-only,
     /// an instance of this struct will be read in from an HttpStream and used
@@ -152,74 +291,228 @@ mod request {
     #[derive(Debug, Clone, PartialEq)]
     pub struct HttpRequest {
         pub method: HttpMethod,
-        pub uri: PathBuf,
-        pub http_version: String,
-        pub headers: HashMap<String, String>,
+        pub uri: RequestTarget,
+        pub http_version: HttpVersion,
+        pub headers: HttpHeaders,
         pub body: Option<Vec<u8>>,
+        /// Trailer fields sent after a chunked body. Empty for any other body.
+        pub trailers: HttpHeaders,
+        /// Parameters captured from the path by the Router. Empty unless the request was routed.
+        pub params: HashMap<String, String>,
+    }
+
+    impl HttpRequest {
+        /// Whether the body is sent with the chunked transfer coding.
+        pub fn is_chunked(&self) -> bool {
+            self.headers.get_combined("transfer-encoding")
+                .map(|v| v.eq_ignore_ascii_case("chunked"))
+                .unwrap_or(false)
+        }
+
+        /// Whether the client waits for a `100 Continue` before sending the body. The
+        /// expectation is ignored for HTTP/1.0 clients, which can't know to send it.
+        pub fn expects_continue(&self) -> bool {
+            self.http_version >= HttpVersion::Http11 && self.headers.get_all("expect")
+                .iter()
+                .any(|v| v.split(',').any(|e| e.trim().eq_ignore_ascii_case("100-continue")))
+        }
+
+        /// Writes the request to writer as it is sent on the wire. A body is sent chunked,
+        /// with the trailers, if the headers say so. Otherwise a `Content-Length` is added
+        /// for the body when the headers don't have one.
+        pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            if self.http_version == HttpVersion::Http09 {
+                return write!(writer, "{} {}\r\n", self.method.as_str(), self.uri);
+            }
+
+            write!(writer, "{} {} {}\r\n", self.method.as_str(), self.uri, self.http_version.as_str())?;
+            for (k, v) in self.headers.iter() {
+                write!(writer, "{}: {}\r\n", k, v)?;
+            }
+
+            let chunked = self.is_chunked();
+            if let Some(data) = &self.body {
+                if !chunked && !self.headers.contains_key("content-length") {
+                    write!(writer, "content-length: {}\r\n", data.len())?;
+                }
+            }
+            writer.write_all(b"\r\n")?;
+
+            if chunked {
+                write_chunk(writer, self.body.as_deref().unwrap_or(&[]))?;
+                writer.write_all(b"0\r\n")?;
+                write_trailers(writer, &self.trailers)
+            } else {
+                writer.write_all(self.body.as_deref().unwrap_or(&[]))
+            }
+        }
     }
 
+    /// Renders the request for display. Bodies which aren't UTF-8 are mangled, use
+    /// `HttpRequest::write_to` to send a request.
     impl From<HttpRequest> for String {
         fn from(req: HttpRequest) -> Self {
-            let header_list: Vec<String> = req.headers
-                .into_iter()
-                .map(|(k, v)| format!("{}: {}", k, v))
-                .collect();
-
-            let body_str = match req.body {
-                Some(data) => format!("\r\n{}", String::from_utf8_lossy(&data)),
-                None => "".to_string()
-            };
-
-            format!("{} {} {}\r\n{}\r\n{}",
-                    req.method.as_str(),
-                    req.uri.to_str().unwrap(),
-                    req.http_version,
-                    header_list.join("\r\n"),
-                    body_str
-            )
+            let mut data: Vec<u8> = vec![];
+            req.write_to(&mut data).expect("writing to a Vec can't fail");
+            String::from_utf8_lossy(&data).into_owned()
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct HttpVersion(pub String);
+mod version {
+    /// The HTTP-Version of a request or response.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum HttpVersion {
+        /// A simple request, which is a Request-Line with no version, headers or body.
+        /// Its response is the bare body.
+        Http09,
+        Http10,
+        #[default]
+        Http11,
+        Http2,
+    }
+
+    impl HttpVersion {
+        pub fn as_str(&self) -> &str {
+            match self {
+                HttpVersion::Http09 => "HTTP/0.9",
+                HttpVersion::Http10 => "HTTP/1.0",
+                HttpVersion::Http11 => "HTTP/1.1",
+                HttpVersion::Http2 => "HTTP/2",
+            }
+        }
+
+        /// Whether a connection with this version persists unless asked to close.
+        pub fn keeps_alive(&self) -> bool {
+            *self >= HttpVersion::Http11
+        }
+
+        /// Whether this version understands `Transfer-Encoding: chunked`.
+        pub fn supports_chunked(&self) -> bool {
+            *self >= HttpVersion::Http11
+        }
+
+        /// Whether value has the syntax of an HTTP-Version in a message, `HTTP/<major>.<minor>`,
+        /// whether or not it is a version this crate knows.
+        pub fn is_well_formed(value: &str) -> bool {
+            value.contains('.') && Self::numbers(value).is_some()
+        }
+
+        /// private: The major and minor version of `HTTP/<major>.<minor>`, the minor defaulting to 0.
+        fn numbers(value: &str) -> Option<(&str, &str)> {
+            value.strip_prefix("HTTP/")
+                .map(|v| v.split_once('.').unwrap_or((v, "0")))
+                .filter(|(major, minor)| {
+                    !major.is_empty() && !minor.is_empty()
+                        && major.chars().chain(minor.chars()).all(|c| c.is_ascii_digit())
+                })
+        }
+    }
+
+    impl std::convert::TryFrom<&str> for HttpVersion {
+        type Error = String;
 
-impl HttpVersion {
-    pub fn default() -> Self {
-        Self("HTTP/1.1".to_string())
+        /// Parses an HTTP-Version of the form `HTTP/<major>.<minor>`. Minor versions of HTTP/1
+        /// newer than 1.1 are treated as 1.1, as they must be backwards compatible.
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            match HttpVersion::numbers(value) {
+                Some(("0", "9")) => Ok(HttpVersion::Http09),
+                Some(("1", "0")) => Ok(HttpVersion::Http10),
+                Some(("1", _)) => Ok(HttpVersion::Http11),
+                Some(("2", "0")) => Ok(HttpVersion::Http2),
+                _ => Err(format!("Invalid HttpVersion: {}", value))
+            }
+        }
     }
 }
 
 mod headers {
     use super::*;
 
-    /// Represents a collection of HTTP headers. Handles setting header keys to lowercase.
-    #[derive(Clone, Debug)]
-    pub struct HttpHeaders(pub HashMap<String, String>);
+    /// Represents a collection of HTTP headers. A name may appear more than once, fields are
+    /// kept in the order they were added, and names keep the casing they were added with.
+    /// Lookups ignore the case of names.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct HttpHeaders(Vec<(String, String)>);
 
     #[allow(dead_code)]
     impl HttpHeaders {
         pub fn new() -> Self {
-            HttpHeaders(HashMap::new())
+            HttpHeaders(Vec::new())
         }
 
+        /// Sets key to value, replacing every existing field with that name. The field keeps
+        /// the position of the first one it replaces.
         pub fn insert(&mut self, key: &str, value: &str) {
-            self.0.insert(key.to_lowercase(), value.to_string());
+            let field = (key.to_string(), value.to_string());
+            match self.0.iter().position(|(k, _)| k.eq_ignore_ascii_case(key)) {
+                Some(index) => {
+                    self.0[index] = field;
+                    let mut rest = self.0.split_off(index + 1);
+                    rest.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+                    self.0.append(&mut rest);
+                },
+                None => self.0.push(field)
+            }
+        }
+
+        /// Adds another field named key, keeping any existing ones.
+        pub fn append(&mut self, key: &str, value: &str) {
+            self.0.push((key.to_string(), value.to_string()));
         }
 
+        /// The value of the first field named key.
         pub fn get(&self, key: &str) -> Option<&String> {
-            self.0.get(&key.to_lowercase())
+            self.0.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
         }
 
-        pub fn contains_key(&mut self, key: &str) -> bool {
-            self.0.contains_key(&key.to_lowercase())
+        /// The values of every field named key, in order.
+        pub fn get_all(&self, key: &str) -> Vec<&str> {
+            self.0.iter().filter(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()).collect()
+        }
+
+        /// The values of every field named key joined into one comma separated list, which
+        /// has the same meaning as the separate fields. `Set-Cookie` values may contain commas,
+        /// so they can't be combined, and only the first is returned.
+        pub fn get_combined(&self, key: &str) -> Option<String> {
+            if key.eq_ignore_ascii_case("set-cookie") {
+                return self.get(key).cloned();
+            }
+            let values = self.get_all(key);
+            if values.is_empty() {
+                None
+            } else {
+                Some(values.join(", "))
+            }
         }
 
+        pub fn contains_key(&self, key: &str) -> bool {
+            self.0.iter().any(|(k, _)| k.eq_ignore_ascii_case(key))
+        }
+
+        /// Removes every field named key, returning the value of the first.
         pub fn unset(&mut self, key: &str) -> Option<String> {
-            self.0.remove(&key.to_lowercase())
+            let value = self.get(key).cloned();
+            self.0.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+            value
+        }
+
+        /// Iterates over the fields as `(name, value)`, in order.
+        pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+            self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+        }
+
+        pub fn len(&self) -> usize {
+            self.0.len()
         }
 
-        pub fn default() -> Self {
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+    }
+
+    impl Default for HttpHeaders {
+        fn default() -> Self {
             let mut result = Self::new();
             result.insert("accept", "application/json");
             result
@@ -229,22 +522,165 @@ mod headers {
     impl From<HashMap<String, String>> for HttpHeaders {
         fn from(hm: HashMap<String, String>) -> HttpHeaders {
             let mut result = HttpHeaders::new();
-            hm.iter().for_each(|(k, v)| { result.insert(&k.to_lowercase(), v); });
+            hm.iter().for_each(|(k, v)| { result.insert(k, v); });
             result
         }
     }
 }
 
+mod body {
+    use std::fmt;
+    use std::io::{Read, Write};
+
+    /// Size of the chunks a `HttpBody::Reader` of unknown length is sent in.
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    /// The body of an HTTP response. Bodies of unknown length are sent with
+    /// `Transfer-Encoding: chunked`.
+    pub enum HttpBody {
+        /// No body at all.
+        Empty,
+        /// A body held fully in memory.
+        Bytes(Vec<u8>),
+        /// A body streamed from a reader. If `length` is known the body is sent with a
+        /// `Content-Length`, and exactly that many bytes are read.
+        Reader {
+            reader: Box<dyn Read + Send>,
+            length: Option<u64>,
+        },
+        /// A body generated chunk by chunk. Empty chunks are skipped.
+        Chunks(Box<dyn Iterator<Item = Vec<u8>> + Send>),
+    }
+
+    impl HttpBody {
+        /// Gets a body streamed from reader, whose length is unknown.
+        pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Self {
+            HttpBody::Reader { reader: Box::new(reader), length: None }
+        }
+
+        /// Gets a body generated from an iterator of chunks.
+        pub fn from_chunks<I>(chunks: I) -> Self
+            where I: IntoIterator<Item = Vec<u8>>, I::IntoIter: Send + 'static
+        {
+            HttpBody::Chunks(Box::new(chunks.into_iter()))
+        }
+
+        /// The number of bytes in the body, if it is known ahead of time.
+        pub fn content_length(&self) -> Option<u64> {
+            match self {
+                HttpBody::Empty => Some(0),
+                HttpBody::Bytes(data) => Some(data.len() as u64),
+                HttpBody::Reader { length, .. } => *length,
+                HttpBody::Chunks(_) => None,
+            }
+        }
+
+        /// Writes the body as-is, without any transfer coding. A reader of known length is read
+        /// for exactly that many bytes, anything else is written until it ends.
+        pub(crate) fn write_raw<W: Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+            match self {
+                HttpBody::Empty => Ok(()),
+                HttpBody::Bytes(data) => writer.write_all(data),
+                HttpBody::Reader { reader, length: Some(length) } => {
+                    let copied = std::io::copy(&mut reader.take(*length), writer)?;
+                    if copied < *length {
+                        return Err(std::io::ErrorKind::UnexpectedEof.into());
+                    }
+                    Ok(())
+                },
+                HttpBody::Reader { reader, length: None } => std::io::copy(reader, writer).map(|_| ()),
+                HttpBody::Chunks(chunks) => chunks.try_for_each(|chunk| writer.write_all(&chunk)),
+            }
+        }
+
+        /// Writes the body using the chunked transfer coding, up to and including the last-chunk.
+        /// The trailer section and final CRLF are left to the caller.
+        pub(crate) fn write_chunked<W: Write>(&mut self, writer: &mut W) -> std::io::Result<()> {
+            match self {
+                HttpBody::Empty => {},
+                HttpBody::Bytes(data) => write_chunk(writer, data)?,
+                HttpBody::Reader { reader, .. } => {
+                    let mut buf = vec![0u8; CHUNK_SIZE];
+                    loop {
+                        let size = match reader.read(&mut buf) {
+                            Ok(0) => break,
+                            Ok(size) => size,
+                            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                            Err(err) => return Err(err),
+                        };
+                        write_chunk(writer, &buf[..size])?;
+                    }
+                },
+                HttpBody::Chunks(chunks) => {
+                    for chunk in chunks {
+                        write_chunk(writer, &chunk)?;
+                    }
+                },
+            }
+
+            writer.write_all(b"0\r\n")
+        }
+    }
+
+    /// Writes a single chunk of a chunked body. Empty chunks are skipped, as they would end the body.
+    pub(crate) fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> std::io::Result<()> {
+        if chunk.is_empty() { return Ok(()); }
+        write!(writer, "{:x}\r\n", chunk.len())?;
+        writer.write_all(chunk)?;
+        writer.write_all(b"\r\n")
+    }
+
+    /// Writes the trailer section and the CRLF which ends a chunked body.
+    pub(crate) fn write_trailers<W: Write>(writer: &mut W, trailers: &super::HttpHeaders) -> std::io::Result<()> {
+        for (k, v) in trailers.iter() {
+            write!(writer, "{}: {}\r\n", k, v)?;
+        }
+        writer.write_all(b"\r\n")
+    }
+
+    impl fmt::Debug for HttpBody {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                HttpBody::Empty => write!(f, "Empty"),
+                HttpBody::Bytes(data) => f.debug_tuple("Bytes").field(data).finish(),
+                HttpBody::Reader { length, .. } => f.debug_struct("Reader").field("length", length).finish(),
+                HttpBody::Chunks(_) => write!(f, "Chunks"),
+            }
+        }
+    }
+
+    impl From<Vec<u8>> for HttpBody {
+        fn from(data: Vec<u8>) -> Self {
+            HttpBody::Bytes(data)
+        }
+    }
+
+    impl From<Option<Vec<u8>>> for HttpBody {
+        fn from(data: Option<Vec<u8>>) -> Self {
+            match data {
+                Some(data) => HttpBody::Bytes(data),
+                None => HttpBody::Empty,
+            }
+        }
+    }
+}
+
 mod response {
     use super::*;
+    use super::body::write_trailers;
+    use std::io::Write;
 
     /// An HTTP response.
-    #[derive(Debug, Clone)]
+    #[derive(Debug)]
     pub struct HttpResponse {
         pub http_version: HttpVersion,
         pub status_code: HttpStatusCode,
         pub headers: HttpHeaders,
-        body: Option<Vec<u8>>
+        /// Trailer fields, only sent when the body is sent with `Transfer-Encoding: chunked`.
+        pub trailers: HttpHeaders,
+        /// Replaces the standard reason phrase of the status code when set.
+        pub reason_phrase: Option<String>,
+        body: HttpBody
     }
 
     impl HttpResponse {
@@ -252,53 +688,124 @@ mod response {
             http_version: HttpVersion,
             status_code: HttpStatusCode,
             headers: HttpHeaders,
-            body: Option<Vec<u8>>
+            body: impl Into<HttpBody>
         ) -> Self {
             let mut result = Self {
                 http_version,
                 status_code,
                 headers,
-                body: None
+                trailers: HttpHeaders::new(),
+                reason_phrase: None,
+                body: HttpBody::Empty
             };
             result.set_body(body);
             result
         }
 
-        pub fn get_body(&self) -> &Option<Vec<u8>> {
+        /// A response with a small HTML page naming the status code, such as
+        /// `<h1>404 Not Found</h1>`.
+        pub fn error(status_code: HttpStatusCode, headers: HttpHeaders) -> Self {
+            let body = format!("<h1>{} {}</h1>", status_code.0, status_code.description());
+            Self::new(HttpVersion::default(), status_code, headers, Some(body.into_bytes()))
+        }
+
+        pub fn get_body(&self) -> &HttpBody {
             &self.body
         }
 
-        // TODO: headers should be locked into only lowercase inmemory, otherwise lookups with caps will fail
-        // implement newtype
+        /// Takes the body out of the response, leaving it empty. The framing headers are left
+        /// as they were, to be replaced by `set_body`.
+        pub fn take_body(&mut self) -> HttpBody {
+            std::mem::replace(&mut self.body, HttpBody::Empty)
+        }
 
-        /// Sets the body field. Automatically sets the `Content-Length` header.
-        pub fn set_body(&mut self, body: Option<Vec<u8>>) {
-            if let Some(data) = &body {
-                self.headers.insert("content-length", &data.len().to_string());
+        /// Sets the body field. Automatically sets the `Content-Length` header when the length
+        /// of the body is known, and `Transfer-Encoding: chunked` otherwise.
+        pub fn set_body(&mut self, body: impl Into<HttpBody>) {
+            let body = body.into();
+            match (&body, body.content_length()) {
+                (HttpBody::Empty, _) => {
+                    self.headers.unset("content-length");
+                    self.headers.unset("transfer-encoding");
+                },
+                (_, Some(length)) => {
+                    self.headers.insert("content-length", &length.to_string());
+                    self.headers.unset("transfer-encoding");
+                },
+                (_, None) => {
+                    self.headers.unset("content-length");
+                    self.headers.insert("transfer-encoding", "chunked");
+                },
             }
             self.body = body;
         }
+
+        /// Whether the body will be sent with the chunked transfer coding.
+        pub fn is_chunked(&self) -> bool {
+            self.headers.get("transfer-encoding").map(|v| v == "chunked").unwrap_or(false)
+        }
+
+        /// The reason phrase sent on the Status-Line. Control characters are stripped from
+        /// custom phrases, so they can't break the framing of the response.
+        pub fn reason(&self) -> String {
+            match &self.reason_phrase {
+                Some(phrase) => phrase.chars().filter(|c| *c == '\t' || !c.is_control()).collect(),
+                None => self.status_code.description().to_string()
+            }
+        }
+
+        /// Writes the response to writer as it is sent on the wire, streaming the body.
+        /// HTTP/0.9 responses are just the body.
+        pub fn write_to<W: Write>(mut self, writer: &mut W) -> std::io::Result<()> {
+            if self.http_version != HttpVersion::Http09 {
+                self.announce_trailers();
+                self.write_head(writer)?;
+            }
+
+            if !self.is_chunked() {
+                return self.body.write_raw(writer);
+            }
+
+            self.body.write_chunked(writer)?;
+            write_trailers(writer, &self.trailers)
+        }
+
+        /// private: Writes the Status-Line and headers, including the blank line which ends the head.
+        fn write_head<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+            // the Status-Line needs exactly three digits
+            if !self.status_code.is_valid() {
+                let message = format!("Invalid HttpStatusCode: {}", self.status_code.0);
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, message));
+            }
+            write!(writer, "{} {:03} {}\r\n",
+                    self.http_version.as_str(),
+                    self.status_code.0,
+                    self.reason()
+            )?;
+            for (k, v) in self.headers.iter() {
+                write!(writer, "{}: {}\r\n", k, v)?;
+            }
+            writer.write_all(b"\r\n")
+        }
+
+        /// private: Announces the trailer field names in the `Trailer` header.
+        fn announce_trailers(&mut self) {
+            if self.is_chunked() && !self.trailers.is_empty() {
+                let names: Vec<&str> = self.trailers.iter().map(|(k, _)| k).collect();
+                let names = names.join(", ");
+                self.headers.insert("trailer", &names);
+            }
+        }
     }
 
+    /// Renders the response for display. Bodies which aren't UTF-8 are mangled, use
+    /// `HttpResponse::write_to` to send a response.
     impl From<HttpResponse> for String {
         fn from(res: HttpResponse) -> Self {
-            let header_list: Vec<String> = res.headers.0
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, v))
-                .collect();
-
-            let body_str: String = match res.get_body() {
-                Some(body ) => String::from_utf8_lossy(&body).to_string(),
-                None => "".to_string()
-            };
-
-            format!("{} {} {}\r\n{}\r\n\r\n{}",
-                    res.http_version.0.as_str(),
-                    res.status_code.0.to_string(),
-                    res.status_code.description(),
-                    header_list.join("\r\n"),
-                    body_str
-            )
+            let mut data: Vec<u8> = vec![];
+            // writing to a Vec only fails if a streamed body fails, in which case we keep what we have
+            let _ = res.write_to(&mut data);
+            String::from_utf8_lossy(&data).into_owned()
         }
     }
 }
@@ -306,26 +813,164 @@ mod response {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use std::convert::TryInto;
 
     #[test]
     fn string_from_request() {
         let expected = "GET /logo.gif HTTP/1.1\r\ncontent-length: 13\r\n\r\nHello, World!";
 
-        let mut headers = HashMap::new();
-        headers.insert("content-length".to_string(), "13".to_string());
+        let mut headers = HttpHeaders::new();
+        headers.insert("content-length", "13");
 
         let request = HttpRequest {
             method: HttpMethod::GET,
-            uri: PathBuf::from("/logo.gif"),
-            http_version: String::from("HTTP/1.1"),
+            uri: "/logo.gif".try_into().unwrap(),
+            http_version: HttpVersion::Http11,
             headers,
-            body: Some("Hello, World!".into())
+            body: Some("Hello, World!".into()),
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
         };
 
         assert_eq!(String::from(request), expected);
     }
 
+    #[test]
+    fn http_method_round_trip() {
+        use std::convert::TryFrom;
+
+        let methods = ["OPTIONS", "GET", "HEAD", "POST", "PUT", "DELETE", "TRACE", "CONNECT", "PATCH", "PROPFIND"];
+        for method in methods.iter() {
+            assert_eq!(HttpMethod::try_from(*method).unwrap().as_str(), *method);
+        }
+
+        assert_eq!(HttpMethod::try_from("PUT"), Ok(HttpMethod::PUT));
+        assert_eq!(HttpMethod::try_from("get"), Ok(HttpMethod::Extension("get".to_string())));
+        assert!(HttpMethod::try_from("").is_err());
+        assert!(HttpMethod::try_from("GE(T").is_err());
+    }
+
+    #[test]
+    fn http_status_code() {
+        use std::convert::TryFrom;
+
+        assert_eq!(HttpStatusCode::NOT_FOUND, HttpStatusCode(404));
+        assert_eq!(HttpStatusCode(405).description(), "Method Not Allowed");
+        assert_eq!(HttpStatusCode(500).description(), "Internal Server Error");
+        assert_eq!(HttpStatusCode(499).reason_phrase(), None);
+        assert_eq!(HttpStatusCode(499).description(), "Client Error");
+        assert_eq!(HttpStatusCode(999).description(), "");
+
+        assert!(HttpStatusCode::CONTINUE.is_informational());
+        assert!(HttpStatusCode::NO_CONTENT.is_success());
+        assert!(HttpStatusCode::FOUND.is_redirection());
+        assert!(HttpStatusCode::GONE.is_client_error());
+        assert!(HttpStatusCode::BAD_GATEWAY.is_server_error());
+        assert!(!HttpStatusCode::OK.is_client_error());
+
+        assert_eq!(HttpStatusCode::try_from(299), Ok(HttpStatusCode(299)));
+        assert!(HttpStatusCode::try_from(99).is_err());
+        assert!(HttpStatusCode::try_from(1000).is_err());
+    }
+
+    #[test]
+    fn custom_reason_phrase() {
+        let response = HttpResponse::new(HttpVersion::default(), HttpStatusCode(299), HttpHeaders::new(), None);
+        assert!(String::from(response).starts_with("HTTP/1.1 299 Success\r\n"));
+
+        let mut response = HttpResponse::new(HttpVersion::default(), HttpStatusCode(299), HttpHeaders::new(), None);
+        response.reason_phrase = Some("All\r\nGood".to_string());
+        assert!(String::from(response).starts_with("HTTP/1.1 299 AllGood\r\n"));
+    }
+
+    #[test]
+    fn http_version() {
+        use std::convert::TryFrom;
+
+        assert_eq!(HttpVersion::try_from("HTTP/1.0"), Ok(HttpVersion::Http10));
+        assert_eq!(HttpVersion::try_from("HTTP/1.1"), Ok(HttpVersion::Http11));
+        assert_eq!(HttpVersion::try_from("HTTP/1.2"), Ok(HttpVersion::Http11));
+        assert_eq!(HttpVersion::try_from("HTTP/2"), Ok(HttpVersion::Http2));
+        assert_eq!(HttpVersion::try_from("HTTP/2.0").unwrap().as_str(), "HTTP/2");
+        assert!(HttpVersion::try_from("HTTP/3.0").is_err());
+        assert!(HttpVersion::try_from("HTTP/1.x").is_err());
+        assert!(HttpVersion::try_from("HTTX/1.1").is_err());
+
+        assert!(HttpVersion::is_well_formed("HTTP/3.0"));
+        assert!(!HttpVersion::is_well_formed("HTTP/1"));
+        assert!(!HttpVersion::is_well_formed("HTTP/1.x"));
+
+        assert!(HttpVersion::Http10 < HttpVersion::Http11);
+        assert!(!HttpVersion::Http10.keeps_alive());
+        assert!(HttpVersion::Http11.supports_chunked());
+    }
+
+    #[test]
+    fn binary_response_body() {
+        let body: Vec<u8> = (0..=255).collect();
+        let response = HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode::OK,
+            HttpHeaders::new(),
+            Some(body.clone())
+        );
+
+        let mut data: Vec<u8> = vec![];
+        response.write_to(&mut data).unwrap();
+        assert!(data.starts_with(b"HTTP/1.1 200 OK\r\ncontent-length: 256\r\n\r\n"));
+        assert!(data.ends_with(&body));
+    }
+
+    #[test]
+    fn error_response() {
+        let mut headers = HttpHeaders::new();
+        headers.insert("allow", "GET");
+        let response = HttpResponse::error(HttpStatusCode::METHOD_NOT_ALLOWED, headers);
+        assert_eq!(
+            String::from(response),
+            "HTTP/1.1 405 Method Not Allowed\r\nallow: GET\r\ncontent-length: 31\r\n\r\n<h1>405 Method Not Allowed</h1>"
+        );
+    }
+
+    #[test]
+    fn invalid_status_code_not_written() {
+        for code in [-5, 0, 99, 1000] {
+            let response = HttpResponse::new(HttpVersion::default(), HttpStatusCode(code), HttpHeaders::new(), None);
+            let mut data: Vec<u8> = vec![];
+            let err = response.write_to(&mut data).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn write_request() {
+        let mut headers = HttpHeaders::new();
+        headers.insert("Host", "example.com");
+        let mut request = HttpRequest {
+            method: HttpMethod::PUT,
+            uri: "/upload".try_into().unwrap(),
+            http_version: HttpVersion::Http11,
+            headers,
+            body: Some(vec![0, 159, 255]),
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
+        };
+
+        let mut data: Vec<u8> = vec![];
+        request.write_to(&mut data).unwrap();
+        assert_eq!(data, b"PUT /upload HTTP/1.1\r\nHost: example.com\r\ncontent-length: 3\r\n\r\n\x00\x9f\xff".to_vec());
+
+        request.headers.insert("Transfer-Encoding", "chunked");
+        request.trailers.insert("Expires", "never");
+        let mut data: Vec<u8> = vec![];
+        request.write_to(&mut data).unwrap();
+        assert!(data.ends_with(b"Transfer-Encoding: chunked\r\n\r\n3\r\n\x00\x9f\xff\r\n0\r\nExpires: never\r\n\r\n"));
+
+        let mut parser = crate::parser::HttpParser::new(&data[..]);
+        assert_eq!(parser.parse_http_request().unwrap(), request);
+    }
+
     #[test]
     fn http_headers() {
         let mut headers = HttpHeaders::new();
@@ -354,4 +999,76 @@ mod tests {
         assert!(headers.contains_key("Content-Length"));
         assert!(headers.contains_key("content-length"));
     }
+
+    #[test]
+    fn http_headers_multiple_values() {
+        let mut headers = HttpHeaders::new();
+        headers.insert("Via", "1.1 a");
+        headers.append("Set-Cookie", "a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT");
+        headers.append("via", "1.1 b");
+        headers.append("Set-Cookie", "b=2");
+
+        assert_eq!(headers.get("via"), Some(&"1.1 a".to_string()));
+        assert_eq!(headers.get_all("VIA"), vec!["1.1 a", "1.1 b"]);
+        assert_eq!(headers.get_combined("Via"), Some("1.1 a, 1.1 b".to_string()));
+        assert_eq!(headers.get_combined("set-cookie"), Some("a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT".to_string()));
+        assert_eq!(headers.get_combined("missing"), None);
+
+        let names: Vec<&str> = headers.iter().map(|(k, _)| k).collect();
+        assert_eq!(names, vec!["Via", "Set-Cookie", "via", "Set-Cookie"]);
+
+        headers.insert("VIA", "1.1 c");
+        let fields: Vec<(&str, &str)> = headers.iter().collect();
+        assert_eq!(fields, vec![
+            ("VIA", "1.1 c"),
+            ("Set-Cookie", "a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("Set-Cookie", "b=2")
+        ]);
+
+        assert_eq!(headers.unset("set-cookie"), Some("a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT".to_string()));
+        assert_eq!(headers.len(), 1);
+    }
+
+    #[test]
+    fn string_from_response() {
+        let response = HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode(200),
+            HttpHeaders::new(),
+            Some(b"Hello, World!".to_vec())
+        );
+
+        assert_eq!(String::from(response), "HTTP/1.1 200 OK\r\ncontent-length: 13\r\n\r\nHello, World!");
+    }
+
+    #[test]
+    fn string_from_chunked_response() {
+        let chunks = vec![b"Hello, ".to_vec(), vec![], b"World!".to_vec()];
+        let mut response = HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode(200),
+            HttpHeaders::new(),
+            HttpBody::from_chunks(chunks)
+        );
+        response.trailers.insert("Expires", "never");
+
+        let string = String::from(response);
+        assert!(string.contains("transfer-encoding: chunked\r\n"));
+        assert!(string.contains("trailer: Expires\r\n"));
+        assert!(!string.contains("content-length"));
+        assert!(string.ends_with("\r\n\r\n7\r\nHello, \r\n6\r\nWorld!\r\n0\r\nExpires: never\r\n\r\n"));
+    }
+
+    #[test]
+    fn string_from_reader_response() {
+        let mut response = HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode(200),
+            HttpHeaders::new(),
+            None
+        );
+        response.set_body(HttpBody::Reader { reader: Box::new(&b"Hello, World!"[..]), length: Some(5) });
+
+        assert_eq!(String::from(response), "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nHello");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::files::StaticFiles;
+use crate::middleware::{self, Middleware};
+use crate::request::{HttpRequest, HttpResponse, HttpMethod, HttpVersion, HttpStatusCode, HttpHeaders};
+use crate::server::RequestHandler;
+use crate::uri::RequestTarget;
+
+/// One segment of a route's path pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Matches exactly this segment.
+    Literal(String),
+    /// `:name`, matches any one segment.
+    Param(String),
+    /// `*name`, matches all remaining segments, even none. Only valid as the last segment.
+    Wildcard(String),
+}
+
+/// A path pattern such as `/users/:id` or `/static/*rest`.
+#[derive(Debug, Clone, PartialEq)]
+struct PathPattern(Vec<Segment>);
+
+impl PathPattern {
+    fn parse(pattern: &str) -> Self {
+        let segments: Vec<Segment> = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                if let Some(name) = segment.strip_prefix(':') {
+                    Segment::Param(name.to_string())
+                } else if let Some(name) = segment.strip_prefix('*') {
+                    Segment::Wildcard(name.to_string())
+                } else {
+                    Segment::Literal(segment.to_string())
+                }
+            })
+            .collect();
+
+        let wildcards = segments.iter().position(|s| matches!(s, Segment::Wildcard(_)));
+        if let Some(index) = wildcards {
+            assert!(index == segments.len() - 1, "wildcard must be the last segment of {}", pattern);
+        }
+
+        PathPattern(segments)
+    }
+
+    /// Matches the percent-decoded segments of a path, returning the captured parameters.
+    fn matches(&self, path: &[String]) -> Option<HashMap<String, String>> {
+        let mut params = HashMap::new();
+        let mut path = path.iter();
+
+        for segment in self.0.iter() {
+            match segment {
+                Segment::Literal(literal) => {
+                    if path.next() != Some(literal) { return None; }
+                },
+                Segment::Param(name) => {
+                    params.insert(name.clone(), path.next()?.clone());
+                },
+                Segment::Wildcard(name) => {
+                    let rest: Vec<&str> = path.by_ref().map(|s| s.as_str()).collect();
+                    params.insert(name.clone(), rest.join("/"));
+                },
+            }
+        }
+
+        match path.next() {
+            Some(_) => None,
+            None => Some(params)
+        }
+    }
+}
+
+struct Route {
+    method: HttpMethod,
+    pattern: PathPattern,
+    handler: Arc<RequestHandler>,
+}
+
+/// Dispatches requests to handlers by method and path. Patterns are made of `/` separated
+/// segments, where `:name` captures one segment and a final `*name` captures the rest of the
+/// path. Captures are available in `HttpRequest::params`.
+///
+/// Routes are tried in the order they were registered. A path which matches no route gets a
+/// 404, and one which matches only routes for other methods gets a 405 with an `Allow` header.
+/// HEAD requests without a route of their own are handled by the GET route, and OPTIONS
+/// requests, including `OPTIONS *`, are answered with the `Allow` header.
+///
+/// Middleware added to a router runs around every request it handles. Routers can be nested
+/// under a prefix, so that middleware applies to a sub-tree of routes only.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self { routes: Vec::new(), middleware: Vec::new() }
+    }
+
+    /// Adds middleware around every request this router handles, including its 404 and 405
+    /// responses. Middleware runs in the order it was added.
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Mounts the routes of router under prefix, so that `/:id` nested at `/users` matches
+    /// `/users/:id`. The middleware of router only runs for its own routes.
+    pub fn nest(&mut self, prefix: &str, router: Router) -> &mut Self {
+        let prefix = PathPattern::parse(prefix);
+        assert!(
+            !prefix.0.iter().any(|s| matches!(s, Segment::Wildcard(_))),
+            "a nested router's prefix can't have a wildcard"
+        );
+
+        for route in router.routes {
+            let mut segments = prefix.0.clone();
+            segments.extend(route.pattern.0);
+            self.routes.push(Route {
+                method: route.method,
+                pattern: PathPattern(segments),
+                handler: middleware::wrap(route.handler, &router.middleware),
+            });
+        }
+        self
+    }
+
+    /// Registers handler for requests with method whose path matches pattern.
+    pub fn route<F>(&mut self, method: HttpMethod, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.routes.push(Route {
+            method,
+            pattern: PathPattern::parse(pattern),
+            handler: Arc::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.route(HttpMethod::GET, pattern, handler)
+    }
+
+    pub fn post<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.route(HttpMethod::POST, pattern, handler)
+    }
+
+    pub fn put<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.route(HttpMethod::PUT, pattern, handler)
+    }
+
+    pub fn patch<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.route(HttpMethod::PATCH, pattern, handler)
+    }
+
+    pub fn delete<F>(&mut self, pattern: &str, handler: F) -> &mut Self
+        where F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static
+    {
+        self.route(HttpMethod::DELETE, pattern, handler)
+    }
+
+    /// Serves the files under files' root at prefix, so that `/static/app.js` mounted at
+    /// `/static` serves `app.js`. The rest of the path is captured as the `path` param.
+    /// Conditional requests are answered with a 304 or 412 where their preconditions fail,
+    /// and range requests with a 206 or 416.
+    pub fn files(&mut self, prefix: &str, files: StaticFiles) -> &mut Self {
+        let pattern = format!("{}/*path", prefix.trim_end_matches('/'));
+        self.get(&pattern, move |request| {
+            let path = request.params.get("path").map(|p| p.as_str()).unwrap_or("");
+            files.serve_encoded(path, request.headers.get_combined("accept-encoding").as_deref())
+                .evaluate_preconditions(&request)
+                .apply_range(&request)
+        })
+    }
+
+    /// The methods with a route matching path, in the order they were registered. HEAD is
+    /// implied by GET, and OPTIONS by any route, as the router answers those itself.
+    pub fn allowed_methods(&self, path: &[String]) -> Vec<HttpMethod> {
+        let routes = self.routes.iter().filter(|route| route.pattern.matches(path).is_some());
+        Self::with_implied_methods(routes.map(|route| &route.method))
+    }
+
+    /// private: Deduplicates methods, adding the HEAD and OPTIONS the router handles itself.
+    fn with_implied_methods<'a, I: Iterator<Item = &'a HttpMethod>>(registered: I) -> Vec<HttpMethod> {
+        let mut methods: Vec<HttpMethod> = vec![];
+        for method in registered {
+            if !methods.contains(method) {
+                methods.push(method.clone());
+            }
+        }
+        if methods.is_empty() {
+            return methods;
+        }
+
+        if let (Some(get), false) = (methods.iter().position(|m| *m == HttpMethod::GET), methods.contains(&HttpMethod::HEAD)) {
+            methods.insert(get + 1, HttpMethod::HEAD);
+        }
+        if !methods.contains(&HttpMethod::OPTIONS) {
+            methods.push(HttpMethod::OPTIONS);
+        }
+        methods
+    }
+
+    /// Dispatches request to the first matching route, through the router's middleware.
+    pub fn handle(&self, request: HttpRequest) -> HttpResponse {
+        middleware::run(&self.middleware, request, &|request| self.dispatch(request))
+    }
+
+    fn dispatch(&self, mut request: HttpRequest) -> HttpResponse {
+        // `OPTIONS *` asks about the server as a whole
+        if request.method == HttpMethod::OPTIONS && request.uri == RequestTarget::Asterisk {
+            let allowed = Self::with_implied_methods(self.routes.iter().map(|route| &route.method));
+            return Self::options_response(&allowed);
+        }
+
+        let path = request.uri.segments();
+        let found = self.find(&request.method, &path).or_else(|| match request.method {
+            // the server leaves the body out of the response
+            HttpMethod::HEAD => self.find(&HttpMethod::GET, &path),
+            _ => None
+        });
+        if let Some((route, params)) = found {
+            request.params = params;
+            return (route.handler)(request);
+        }
+
+        let allowed = self.allowed_methods(&path);
+        if allowed.is_empty() {
+            return HttpResponse::error(HttpStatusCode::NOT_FOUND, HttpHeaders::new());
+        }
+        if request.method == HttpMethod::OPTIONS {
+            return Self::options_response(&allowed);
+        }
+
+        let mut headers = HttpHeaders::new();
+        headers.insert("allow", &Self::allow(&allowed));
+        HttpResponse::error(HttpStatusCode::METHOD_NOT_ALLOWED, headers)
+    }
+
+    /// private: The first route for method matching path, and the parameters it captures.
+    fn find(&self, method: &HttpMethod, path: &[String]) -> Option<(&Route, HashMap<String, String>)> {
+        self.routes.iter()
+            .filter(|route| route.method == *method)
+            .find_map(|route| route.pattern.matches(path).map(|params| (route, params)))
+    }
+
+    fn allow(methods: &[HttpMethod]) -> String {
+        let methods: Vec<&str> = methods.iter().map(|m| m.as_str()).collect();
+        methods.join(", ")
+    }
+
+    fn options_response(allowed: &[HttpMethod]) -> HttpResponse {
+        let mut headers = HttpHeaders::new();
+        headers.insert("allow", &Self::allow(allowed));
+        HttpResponse::new(HttpVersion::default(), HttpStatusCode::NO_CONTENT, headers, None)
+    }
+
+    /// Gets a request handler dispatching through this router, to be set on an HttpServer.
+    pub fn into_handler(self) -> Arc<RequestHandler> {
+        Arc::new(move |request| self.handle(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::Next;
+    use crate::test_util::{request, ok, body};
+
+    /// A handler which answers with the params it was given, sorted by name.
+    fn echo_params(req: HttpRequest) -> HttpResponse {
+        let mut params: Vec<String> = req.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        params.sort();
+        ok(&[], Some(params.join("&").into_bytes()))
+    }
+
+    fn router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/", echo_params)
+            .get("/users/:id", echo_params)
+            .delete("/users/:id", echo_params)
+            .get("/users/:id/files/:name", echo_params)
+            .get("/static/*rest", echo_params);
+        router
+    }
+
+    #[test]
+    fn matches_params() {
+        let router = router();
+        assert_eq!(body(router.handle(request("GET / HTTP/1.1\r\n\r\n"))), "");
+        assert_eq!(body(router.handle(request("GET /users/42 HTTP/1.1\r\n\r\n"))), "id=42");
+        assert_eq!(body(router.handle(request("GET /users/42/ HTTP/1.1\r\n\r\n"))), "id=42");
+        assert_eq!(body(router.handle(request("GET /users/j%20d/files/a.txt HTTP/1.1\r\n\r\n"))), "id=j d&name=a.txt");
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        let router = router();
+        assert_eq!(body(router.handle(request("GET /static/css/site.css HTTP/1.1\r\n\r\n"))), "rest=css/site.css");
+        assert_eq!(body(router.handle(request("GET /static HTTP/1.1\r\n\r\n"))), "rest=");
+    }
+
+    #[test]
+    fn not_found_and_method_not_allowed() {
+        let router = router();
+
+        let response = router.handle(request("GET /nowhere HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_FOUND);
+
+        let response = router.handle(request("GET /users/42/extra HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_FOUND);
+
+        let response = router.handle(request("POST /users/42 HTTP/1.1\r\nContent-Length: 0\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers.get("allow"), Some(&"GET, HEAD, DELETE, OPTIONS".to_string()));
+    }
+
+    #[test]
+    fn head_and_options() {
+        let mut router = router();
+        router.route(HttpMethod::HEAD, "/head", |_req| {
+            HttpResponse::new(HttpVersion::default(), HttpStatusCode::NO_CONTENT, HttpHeaders::new(), None)
+        });
+        router.get("/head", echo_params);
+
+        assert_eq!(body(router.handle(request("HEAD /users/42 HTTP/1.1\r\n\r\n"))), "id=42");
+        let response = router.handle(request("HEAD /head HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NO_CONTENT);
+
+        let response = router.handle(request("OPTIONS /users/42 HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("allow"), Some(&"GET, HEAD, DELETE, OPTIONS".to_string()));
+
+        let response = router.handle(request("OPTIONS /head HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.headers.get("allow"), Some(&"HEAD, GET, OPTIONS".to_string()));
+
+        let response = router.handle(request("OPTIONS * HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NO_CONTENT);
+        assert_eq!(response.headers.get("allow"), Some(&"GET, DELETE, HEAD, OPTIONS".to_string()));
+
+        let response = router.handle(request("OPTIONS /nowhere HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn nested_middleware() {
+        let tag = |name: &'static str| move |req: HttpRequest, next: Next| {
+            let mut res = next(req);
+            res.headers.append("x-middleware", name);
+            res
+        };
+
+        let mut api = Router::new();
+        api.get("/users/:id", echo_params).add_middleware(tag("api"));
+
+        let mut router = router();
+        router.nest("/api", api).add_middleware(tag("root"));
+
+        let response = router.handle(request("GET /api/users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.headers.get_all("x-middleware"), vec!["api", "root"]);
+        assert_eq!(body(response), "id=7");
+
+        let response = router.handle(request("GET /users/7 HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.headers.get_all("x-middleware"), vec!["root"]);
+
+        let response = router.handle(request("GET /api/nowhere HTTP/1.1\r\n\r\n"));
+        assert_eq!(response.status_code, HttpStatusCode::NOT_FOUND);
+        assert_eq!(response.headers.get_all("x-middleware"), vec!["root"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn wildcard_must_be_last() {
+        Router::new().get("/static/*rest/more", echo_params);
+    }
+}
@@ -1,67 +1,918 @@
-use std::net::{TcpStream, TcpListener};
+use std::convert::TryFrom;
+use std::net::{SocketAddr, TcpStream, TcpListener, ToSocketAddrs};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::request::{HttpRequest, HttpResponse, HttpVersion, HttpStatusCode, HttpHeaders};
+use crate::middleware::{self, Middleware};
+use crate::parser::{ParseError, ParseLimits};
+use crate::pool::WorkerPool;
+use crate::shutdown::ShutdownHandle;
+use crate::request::{HttpRequest, HttpResponse, HttpMethod, HttpVersion, HttpStatusCode, HttpHeaders, HttpBody};
 use crate::stream::HttpStream;
 use std::sync::Arc;
 
-type RequestHandler = dyn Fn(HttpRequest) -> HttpResponse + Send + Sync;
+/// How long to wait before accepting again after an accept fails.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+pub type RequestHandler = dyn Fn(HttpRequest) -> HttpResponse + Send + Sync;
+
+/// Decides, from its head, whether a request may send its body. Returning a response refuses it.
+pub type ContinueHandler = dyn Fn(&HttpRequest) -> Option<HttpResponse> + Send + Sync;
+
+/// Told about a failure to accept a connection, other than the client hanging up first.
+pub type AcceptErrorHandler = dyn Fn(&std::io::Error) + Send + Sync;
+
+/// What the server does with a new connection when every worker is busy and the queue of
+/// waiting connections is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueFullPolicy {
+    /// Stop accepting connections until there is room in the queue.
+    Block,
+    /// Answer the connection with `503 Service Unavailable` and close it.
+    Reject,
+}
 
 pub struct HttpServer {
     listening: bool,
+    listeners: Vec<TcpListener>,
+    /// Handle connections on a pool of worker threads, rather than one at a time on the
+    /// listening thread.
     pub multi_threaded: bool,
+    /// The number of worker threads when multi_threaded.
+    pub worker_threads: usize,
+    /// How many accepted connections may wait for a free worker.
+    pub queue_size: usize,
+    pub queue_full_policy: QueueFullPolicy,
     pub request_handler: Arc<RequestHandler>,
+    /// Consulted with the head of a request whose client waits for `100 Continue` before
+    /// sending the body. Returning a response, such as a 417 or 413, sends it in place of the
+    /// `100 Continue` and closes the connection, without the body ever being transmitted.
+    pub continue_handler: Option<Arc<ContinueHandler>>,
+    /// Called when accepting a connection fails, such as when the process is out of file
+    /// descriptors. The server pauses briefly before accepting again either way.
+    pub on_accept_error: Option<Arc<AcceptErrorHandler>>,
+    /// Runs around every request, in order, before request_handler.
+    pub middleware: Vec<Arc<dyn Middleware>>,
+    /// How long a persistent connection may sit idle waiting for its next request.
+    /// `None` waits forever.
+    pub keep_alive_timeout: Option<Duration>,
+    /// How long a client has to send the head of a request once it has started it, before
+    /// being answered with a 408. Keeps slow clients from holding a connection open.
+    pub header_read_timeout: Option<Duration>,
+    /// How long a client has to send the body of a request, once its head is read.
+    pub body_read_timeout: Option<Duration>,
+    /// How long a single write to a client may block.
+    pub write_timeout: Option<Duration>,
+    /// How many requests are served on a single connection before it is closed.
+    /// `Some(1)` disables persistent connections, `None` allows any number.
+    pub max_requests_per_connection: Option<usize>,
+    /// How large a request may be; larger ones are refused with a 413, 414 or 431.
+    pub limits: ParseLimits,
+    /// Decode request bodies sent with a `Content-Encoding` of gzip or deflate before they
+    /// reach the request_handler, up to limits.max_body_size once decoded. Bodies in any
+    /// other coding are refused with a 415.
+    pub decompress_requests: bool,
+    /// How long requests already being handled get to finish once the server is shut down,
+    /// before their connections are closed.
+    pub shutdown_timeout: Duration,
+    shutdown: ShutdownHandle,
+}
+
+/// The settings each connection handler needs, copied out of the HttpServer so that
+/// connections can be handled on other threads.
+#[derive(Clone)]
+struct ConnectionConfig {
+    keep_alive_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    body_read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_requests: Option<usize>,
+    limits: ParseLimits,
+    decompress_requests: bool,
+    continue_handler: Option<Arc<ContinueHandler>>,
+    shutdown: ShutdownHandle,
+}
+
+impl Default for HttpServer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HttpServer {
     pub fn new() -> Self {
         Self {
             listening: false,
+            listeners: Vec::new(),
             multi_threaded: true,
+            worker_threads: 16,
+            queue_size: 64,
+            queue_full_policy: QueueFullPolicy::Reject,
             request_handler: Arc::new(Self::default_request_handler),
+            continue_handler: None,
+            on_accept_error: None,
+            middleware: Vec::new(),
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            header_read_timeout: Some(Duration::from_secs(10)),
+            body_read_timeout: Some(Duration::from_secs(30)),
+            write_timeout: Some(Duration::from_secs(30)),
+            max_requests_per_connection: Some(100),
+            limits: ParseLimits::default(),
+            decompress_requests: false,
+            shutdown_timeout: Duration::from_secs(10),
+            shutdown: ShutdownHandle::new(),
         }
     }
 
+    /// Gets a handle which can stop the server once it is listening.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     fn default_request_handler(_req: HttpRequest) -> HttpResponse {
         let body_bytes = b"<h1>Hello, World!</h1>";
 
-        return HttpResponse::new(
+        HttpResponse::new(
             HttpVersion::default(),
-            HttpStatusCode(200),
+            HttpStatusCode::OK,
             HttpHeaders::default(),
             Some(body_bytes.to_vec())
         )
     }
 
-    fn connection_handler(stream: TcpStream, handler: Arc<RequestHandler>) -> std::io::Result<()> {
+    /// Adds middleware to run around every request, after any already added.
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) {
+        self.middleware.push(Arc::new(middleware));
+    }
+
+    fn connection_config(&self) -> ConnectionConfig {
+        ConnectionConfig {
+            keep_alive_timeout: self.keep_alive_timeout,
+            header_read_timeout: self.header_read_timeout,
+            body_read_timeout: self.body_read_timeout,
+            write_timeout: self.write_timeout,
+            max_requests: self.max_requests_per_connection,
+            limits: self.limits,
+            decompress_requests: self.decompress_requests,
+            continue_handler: self.continue_handler.clone(),
+            shutdown: self.shutdown.clone(),
+        }
+    }
+
+    /// Builds the response sent to the client when its request could not be parsed, in the
+    /// request's version if its head was read. Returns `None` when the connection is unusable
+    /// and should simply be dropped.
+    fn error_response(err: &ParseError, version: Option<HttpVersion>) -> Option<HttpResponse> {
+        let status_code = match err {
+            ParseError::BadRequestLine(_)
+            | ParseError::MalformedHeader(_)
+            | ParseError::InvalidContentLength(_)
+            | ParseError::ConflictingLength
+            | ParseError::InvalidChunk(_)
+            | ParseError::InvalidContentEncoding(_)
+            | ParseError::UnknownMethod(_) => HttpStatusCode::BAD_REQUEST,
+            ParseError::MissingContentLength => HttpStatusCode::LENGTH_REQUIRED,
+            ParseError::UnsupportedTransferEncoding(_) => HttpStatusCode::NOT_IMPLEMENTED,
+            ParseError::BadVersion(_) => HttpStatusCode::HTTP_VERSION_NOT_SUPPORTED,
+            ParseError::RequestLineTooLong => HttpStatusCode::URI_TOO_LONG,
+            ParseError::HeadersTooLarge => HttpStatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            ParseError::BodyTooLarge => HttpStatusCode::CONTENT_TOO_LARGE,
+            ParseError::Timeout => HttpStatusCode::REQUEST_TIMEOUT,
+            ParseError::ExpectationFailed(_) => HttpStatusCode::EXPECTATION_FAILED,
+            ParseError::UnsupportedContentEncoding(_) => HttpStatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ParseError::Io(_) | ParseError::Eof => return None,
+        };
+
+        let mut headers = HttpHeaders::new();
+        headers.insert("connection", "close");
+        if let ParseError::UnsupportedContentEncoding(_) = err {
+            // tell the client which codings it could have used
+            headers.insert("accept-encoding", "gzip, deflate");
+        }
+
+        Some(HttpResponse::new(
+            version.unwrap_or_default().min(HttpVersion::Http11),
+            status_code,
+            headers,
+            Some(err.to_string().into_bytes())
+        ))
+    }
+
+    /// Answers a request which could not be read with its error_response, if it can be answered.
+    fn send_error(stream: &mut HttpStream<TcpStream>, err: &ParseError, version: Option<HttpVersion>) -> std::io::Result<()> {
+        match Self::error_response(err, version) {
+            Some(response) => stream.write_response(response),
+            None => Ok(())
+        }
+    }
+
+    /// Answers a connection there is no room for with a 503, without reading its request.
+    fn reject_connection(stream: TcpStream) -> std::io::Result<()> {
+        let mut headers = HttpHeaders::new();
+        headers.insert("connection", "close");
+        headers.insert("retry-after", "1");
+        let response = HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode::SERVICE_UNAVAILABLE,
+            headers,
+            Some(b"<h1>503 Service Unavailable</h1>".to_vec())
+        );
+        let mut stream = HttpStream::new(stream);
+        // this runs on the listening thread, so don't let a slow client hold it up
+        stream.write_timeout = Some(Duration::from_secs(1));
+        stream.write_response(response)
+    }
+
+    /// Whether a comma separated header, such as `Connection`, contains token.
+    fn has_token(headers: &HttpHeaders, key: &str, token: &str) -> bool {
+        headers.get_all(key)
+            .iter()
+            .any(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+    }
+
+    /// Whether the client wants the connection kept open after this request.
+    /// HTTP/1.1 connections persist unless closed, HTTP/1.0 ones only when asked to,
+    /// and HTTP/0.9 ones never do.
+    fn wants_keep_alive(request: &HttpRequest) -> bool {
+        if request.http_version == HttpVersion::Http09 || Self::has_token(&request.headers, "connection", "close") {
+            return false;
+        }
+        request.http_version.keeps_alive() || Self::has_token(&request.headers, "connection", "keep-alive")
+    }
+
+    fn connection_handler(stream: TcpStream, handler: Arc<RequestHandler>, config: ConnectionConfig) -> std::io::Result<()> {
+        let connection = config.shutdown.track(&stream)?;
         let mut stream: HttpStream<TcpStream> = HttpStream::new(stream);
+        stream.limits = config.limits;
+        stream.idle_timeout = config.keep_alive_timeout;
+        stream.header_timeout = config.header_read_timeout;
+        stream.body_timeout = config.body_read_timeout;
+        stream.write_timeout = config.write_timeout;
+        let mut served: usize = 0;
 
-        let request = stream.read_http().unwrap();
-        let response = handler(request);
+        loop {
+            // once shutting down, don't wait around for another request
+            connection.set_idle(true);
+            if config.shutdown.is_shutdown() {
+                return Ok(());
+            }
+            let mut request = match stream.read_head() {
+                Ok(request) => request,
+                Err(err) => return Self::send_error(&mut stream, &err, None)
+            };
+            connection.set_idle(false);
+            if let Err(err) = request.check_expectations() {
+                return Self::send_error(&mut stream, &err, Some(request.http_version));
+            }
+
+            // the client may be waiting to hear whether to send the body at all
+            let refused = match (&config.continue_handler, request.expects_continue()) {
+                (Some(continue_handler), true) => continue_handler(&request),
+                _ => None
+            };
+            if refused.is_none() {
+                let body = stream.read_body(&mut request).and_then(|_| match config.decompress_requests {
+                    true => request.decode_body(config.limits.max_body_size),
+                    false => Ok(())
+                });
+                if let Err(err) = body {
+                    return Self::send_error(&mut stream, &err, Some(request.http_version));
+                }
+            }
+            served += 1;
+
+            // the unread body of a refused request is still on its way, so don't read on
+            let version = request.http_version;
+            let head = request.method == HttpMethod::HEAD;
+            let mut keep_alive = refused.is_none()
+                && Self::wants_keep_alive(&request)
+                && config.max_requests.map(|max| served < max).unwrap_or(true);
+
+            let mut response = match refused {
+                Some(response) => response,
+                None => handler(request)
+            };
+            keep_alive = keep_alive
+                && !config.shutdown.is_shutdown()
+                && !Self::has_token(&response.headers, "connection", "close");
+
+            // answer in the newest version the client understands
+            response.http_version = response.http_version.min(version);
+            if !head && response.is_chunked() && !version.supports_chunked() {
+                // the end of the body can then only be signalled by closing the connection
+                response.headers.unset("transfer-encoding");
+                keep_alive = false;
+            }
+
+            // a 304 stands in for the client's copy, so describes no representation of its own
+            if response.status_code == HttpStatusCode::NOT_MODIFIED {
+                response.headers.unset("content-type");
+            }
 
-        stream.write(String::from(response).as_bytes());
+            // without a length, the client could only find the end of the body by the connection closing
+            if let HttpBody::Empty = response.get_body() {
+                if !matches!(response.status_code.0, 100..=199 | 204 | 304) {
+                    response.headers.insert("content-length", "0");
+                }
+            }
+
+            // a HEAD gets the headers a GET would have, but no body
+            if head {
+                response.take_body();
+                response.headers.unset("transfer-encoding");
+            }
+
+            if !keep_alive {
+                response.headers.insert("connection", "close");
+            } else if !version.keeps_alive() {
+                response.headers.insert("connection", "keep-alive");
+            }
+
+            stream.write_response(response)?;
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Binds every address addrs resolves to, to be served by `serve`. Port 0 binds a free
+    /// port. Returns the bound addresses, with their actual ports.
+    pub fn bind<A: ToSocketAddrs>(&mut self, addrs: A) -> std::io::Result<Vec<SocketAddr>> {
+        let mut bound = vec![];
+        for addr in addrs.to_socket_addrs()? {
+            bound.push(self.add_listener(TcpListener::bind(addr)?)?);
+        }
+        if bound.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to bind"));
+        }
+        Ok(bound)
+    }
+
+    /// Adds an already bound listener to be served by `serve`, such as one handed over by
+    /// socket activation. Returns the address it is bound to.
+    pub fn add_listener(&mut self, listener: TcpListener) -> std::io::Result<SocketAddr> {
+        let addr = listener.local_addr()?;
+        self.listeners.push(listener);
+        Ok(addr)
+    }
+
+    /// The addresses of the listeners waiting to be served.
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().filter_map(|l| l.local_addr().ok()).collect()
+    }
+
+    /// Binds 127.0.0.1:port and serves it, along with any listeners already added.
+    pub fn listen(&mut self, port: usize) -> std::io::Result<()> {
+        self.check_config()?;
+        let port = u16::try_from(port)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "port out of range"))?;
+        self.bind(("127.0.0.1", port))?;
+        self.serve()
+    }
+
+    /// private: Fails with `InvalidInput` for settings the server can't run with.
+    fn check_config(&self) -> std::io::Result<()> {
+        if self.multi_threaded && self.worker_threads == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "worker_threads must be at least 1"));
+        }
         Ok(())
     }
 
-    pub fn listen(&mut self, port: usize) -> Result<(), ()> {
+    /// private: Reports a failed accept to on_accept_error. Errors which don't go away at
+    /// once, such as running out of file descriptors, get a pause before the next accept
+    /// rather than a busy loop.
+    fn accept_failed(on_accept_error: &Option<Arc<AcceptErrorHandler>>, err: std::io::Error) {
+        use std::io::ErrorKind;
+        // the client hung up before it was accepted, which is no fault of the server
+        if matches!(err.kind(), ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset | ErrorKind::Interrupted) {
+            return;
+        }
+        if let Some(on_accept_error) = on_accept_error {
+            on_accept_error(&err);
+        }
+        thread::sleep(ACCEPT_BACKOFF);
+    }
+
+    /// Accepts connections on every bound listener until the server is shut down. Each
+    /// listener accepts on its own thread, when not multi_threaded they each handle one
+    /// connection at a time.
+    pub fn serve(&mut self) -> std::io::Result<()> {
         // can only listen once
-        if self.listening { return Err(()); }
+        if self.listening {
+            return Err(std::io::Error::other("server is already listening"));
+        }
+        if self.listeners.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "no listener to serve"));
+        }
+        self.check_config()?;
         self.listening = true;
 
-        // accept connections on infinite loop
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap();
+        let listeners = std::mem::take(&mut self.listeners);
+        let shutdown = self.shutdown.clone();
+        for listener in listeners.iter() {
+            shutdown.listening_on(listener.local_addr()?);
+        }
+        if shutdown.is_shutdown() {
+            return Ok(());
+        }
+
+        let handler = middleware::wrap(self.request_handler.clone(), &self.middleware);
+        let config = self.connection_config();
+        let policy = self.queue_full_policy;
+        let pool = match self.multi_threaded {
+            true => {
+                let (handler, config) = (handler.clone(), config.clone());
+                Some(WorkerPool::new(self.worker_threads, self.queue_size, move |stream: TcpStream| {
+                    let _ = Self::connection_handler(stream, handler.clone(), config.clone());
+                }))
+            },
+            false => None
+        };
 
-        for stream in listener.incoming() {
-            let f = self.request_handler.clone();
-            if self.multi_threaded {
-                thread::spawn(move || {
-                    Self::connection_handler(stream.unwrap(), f.clone()).unwrap();
+        // accept connections until shut down
+        let on_accept_error = self.on_accept_error.clone();
+        thread::scope(|scope| {
+            for listener in listeners.iter() {
+                let (pool, shutdown, handler, config) = (&pool, &shutdown, &handler, &config);
+                let on_accept_error = &on_accept_error;
+                scope.spawn(move || {
+                    for stream in listener.incoming() {
+                        if shutdown.is_shutdown() {
+                            break;
+                        }
+                        let stream = match stream {
+                            Ok(stream) => stream,
+                            Err(err) => {
+                                Self::accept_failed(on_accept_error, err);
+                                continue;
+                            }
+                        };
+                        match (pool, policy) {
+                            // the client may have gone away; there is no one left to report this to
+                            (None, _) => { let _ = Self::connection_handler(stream, handler.clone(), config.clone()); },
+                            (Some(pool), QueueFullPolicy::Block) => pool.execute(stream),
+                            (Some(pool), QueueFullPolicy::Reject) => if let Err(stream) = pool.try_execute(stream) {
+                                let _ = Self::reject_connection(stream);
+                            }
+                        }
+                    }
                 });
-            } else {
-                Self::connection_handler(stream.unwrap(), f).unwrap();
             }
+        });
+        drop(listeners);
+
+        // let the requests being handled finish, then close whatever is left
+        let deadline = Instant::now() + self.shutdown_timeout;
+        while shutdown.has_connections() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
         }
+        shutdown.close_connections();
+        drop(pool);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    /// Spawns a thread handling a single connection with handler, returning the client side
+    /// of that connection.
+    fn connect_with(handler: Arc<RequestHandler>, config: ConnectionConfig) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            HttpServer::connection_handler(stream, handler, config).unwrap();
+        });
+        TcpStream::connect(addr).unwrap()
+    }
+
+    /// Spawns a thread handling a single connection with the default request handler.
+    fn connect(config: ConnectionConfig) -> TcpStream {
+        connect_with(Arc::new(HttpServer::default_request_handler), config)
+    }
+
+    /// Reads a single content-length framed response, returning its head.
+    fn read_response<R: BufRead>(reader: &mut R) -> String {
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" || line.is_empty() { break; }
+            head.push_str(&line);
+        }
+        let length: usize = head.lines()
+            .find_map(|l| l.strip_prefix("content-length: "))
+            .map(|l| l.parse().unwrap())
+            .unwrap_or(0);
+        let mut body = vec![0u8; length];
+        reader.read_exact(&mut body).unwrap();
+        head
+    }
+
+    fn config() -> ConnectionConfig {
+        ConnectionConfig {
+            keep_alive_timeout: Some(Duration::from_secs(5)),
+            header_read_timeout: Some(Duration::from_secs(5)),
+            body_read_timeout: Some(Duration::from_secs(5)),
+            write_timeout: Some(Duration::from_secs(5)),
+            max_requests: None,
+            limits: ParseLimits::default(),
+            decompress_requests: false,
+            continue_handler: None,
+            shutdown: ShutdownHandle::new()
+        }
+    }
+
+    #[test]
+    fn keep_alive() {
+        let mut client = connect(config());
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!read_response(&mut reader).contains("connection: close"));
+
+        client.write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+        assert!(read_response(&mut reader).contains("connection: close"));
+
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn pipelining() {
+        let mut client = connect(config());
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(concat!(
+            "POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody",
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET /missing HTTP/1.1\r\nConnection: close\r\n\r\n"
+        ).as_bytes()).unwrap();
+
+        assert!(read_response(&mut reader).starts_with("HTTP/1.1 200 OK"));
+        assert!(read_response(&mut reader).starts_with("HTTP/1.1 200 OK"));
+        assert!(read_response(&mut reader).contains("connection: close"));
+    }
+
+    #[test]
+    fn keep_alive_http_1_0() {
+        let mut client = connect(config());
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(read_response(&mut reader).contains("connection: keep-alive"));
+
+        client.write_all(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+        let head = read_response(&mut reader);
+        assert!(head.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(head.contains("connection: close"));
+    }
+
+    #[test]
+    fn http_0_9() {
+        let mut client = connect(config());
+        client.write_all(b"GET /\r\n").unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "<h1>Hello, World!</h1>");
+    }
+
+    #[test]
+    fn chunked_body_to_http_1_0() {
+        let handler = |_req: HttpRequest| HttpResponse::new(
+            HttpVersion::default(),
+            HttpStatusCode::OK,
+            HttpHeaders::new(),
+            HttpBody::from_chunks(vec![b"Hello, ".to_vec(), b"World!".to_vec()])
+        );
+        let mut client = connect_with(Arc::new(handler), config());
+        client.write_all(b"GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n").unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(response.contains("connection: close\r\n"));
+        assert!(!response.contains("transfer-encoding"));
+        assert!(response.ends_with("\r\n\r\nHello, World!"));
+    }
+
+    #[test]
+    fn shutdown_closes_idle_connections() {
+        let config = config();
+        let shutdown = config.shutdown.clone();
+        let mut client = connect_with(Arc::new(HttpServer::default_request_handler), config);
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!read_response(&mut reader).contains("connection: close"));
+
+        shutdown.shutdown();
+        let mut rest = vec![];
+        reader.read_to_end(&mut rest).unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn shutdown_finishes_busy_connections() {
+        let config = config();
+        let shutdown = config.shutdown.clone();
+        let handler = move |req: HttpRequest| {
+            shutdown.shutdown();
+            HttpServer::default_request_handler(req)
+        };
+        let mut client = connect_with(Arc::new(handler), config);
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        let head = read_response(&mut reader);
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("connection: close"));
+    }
+
+    #[test]
+    fn reject_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        HttpServer::reject_connection(stream).unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.contains("connection: close\r\n"));
+    }
+
+    #[test]
+    fn accept_failed() {
+        let reported = Arc::new(std::sync::Mutex::new(vec![]));
+        let on_accept_error: Option<Arc<AcceptErrorHandler>> = Some(Arc::new({
+            let reported = reported.clone();
+            move |err: &std::io::Error| reported.lock().unwrap().push(err.kind())
+        }));
+
+        HttpServer::accept_failed(&on_accept_error, std::io::ErrorKind::ConnectionAborted.into());
+        let start = Instant::now();
+        HttpServer::accept_failed(&on_accept_error, std::io::Error::other("too many open files"));
+        assert!(start.elapsed() >= ACCEPT_BACKOFF);
+        assert_eq!(*reported.lock().unwrap(), vec![std::io::ErrorKind::Other]);
+    }
+
+    #[test]
+    fn no_worker_threads() {
+        let mut server = HttpServer::new();
+        server.multi_threaded = true;
+        server.worker_threads = 0;
+        assert_eq!(server.listen(0).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert!(server.local_addrs().is_empty());
+
+        server.bind("127.0.0.1:0").unwrap();
+        assert_eq!(server.serve().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut client = connect(config());
+        client.write_all(b"GET / HTTP/2.0\r\n\r\n").unwrap();
+
+        let mut reader = BufReader::new(client);
+        assert!(read_response(&mut reader).starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+    }
+
+    #[test]
+    fn max_requests_per_connection() {
+        let mut client = connect(ConnectionConfig { max_requests: Some(2), ..config() });
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(!read_response(&mut reader).contains("connection: close"));
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(read_response(&mut reader).contains("connection: close"));
+    }
+
+    #[test]
+    fn request_too_large() {
+        let limits = ParseLimits { max_request_line: 32, max_headers: 2, max_header_bytes: 1024, max_body_size: 16 };
+        let config = || ConnectionConfig { limits, ..config() };
+
+        let mut client = connect(config());
+        client.write_all(format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(64)).as_bytes()).unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+
+        let mut client = connect(config());
+        client.write_all(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n").unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        let mut client = connect(config());
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n").unwrap();
+        let head = read_response(&mut BufReader::new(client));
+        assert!(head.starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+        assert!(head.contains("connection: close\r\n"));
+
+        // once the request line is read, the error is in the client's version
+        let mut client = connect(config());
+        client.write_all(b"POST / HTTP/1.0\r\nContent-Length: 17\r\n\r\n").unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.0 413 Content Too Large\r\n"));
+    }
+
+    #[test]
+    fn header_read_timeout() {
+        let config = ConnectionConfig { header_read_timeout: Some(Duration::from_millis(300)), ..config() };
+        let mut client = connect(config);
+
+        // trickling the head in doesn't extend the deadline
+        let start = Instant::now();
+        client.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(50));
+            client.write_all(b"X-Slow: 1\r\n").unwrap();
+        }
+
+        let head = read_response(&mut BufReader::new(client));
+        assert!(head.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        assert!(head.contains("connection: close\r\n"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn body_read_timeout() {
+        let mut client = connect(ConnectionConfig { body_read_timeout: Some(Duration::from_millis(100)), ..config() });
+        client.write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort").unwrap();
+
+        let head = read_response(&mut BufReader::new(client));
+        assert!(head.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+    }
+
+    #[test]
+    fn idle_timeout() {
+        let mut client = connect(ConnectionConfig { keep_alive_timeout: Some(Duration::from_millis(100)), ..config() });
+
+        // an idle connection is closed without a response
+        let mut response = vec![];
+        client.read_to_end(&mut response).unwrap();
+        assert!(response.is_empty());
+    }
+
+    #[test]
+    fn decompress_requests() {
+        let handler = |request: HttpRequest| {
+            HttpResponse::new(HttpVersion::default(), HttpStatusCode::OK, HttpHeaders::new(), request.body)
+        };
+        let config = || ConnectionConfig {
+            decompress_requests: true,
+            limits: ParseLimits { max_body_size: 64, ..ParseLimits::default() },
+            ..config()
+        };
+        let gzip = |data: &[u8]| {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let post = |client: &mut TcpStream, encoding: &str, body: &[u8]| {
+            let head = format!("POST / HTTP/1.1\r\nContent-Encoding: {}\r\nContent-Length: {}\r\n\r\n", encoding, body.len());
+            client.write_all(head.as_bytes()).unwrap();
+            client.write_all(body).unwrap();
+        };
+
+        let mut client = connect_with(Arc::new(handler), config());
+        post(&mut client, "gzip", &gzip(b"{\"compressed\": true}"));
+        let mut reader = BufReader::new(client);
+        assert!(read_response(&mut reader).contains("content-length: 20\r\n"));
+
+        // a small upload which decodes to more than the limit
+        let mut client = connect_with(Arc::new(handler), config());
+        post(&mut client, "gzip", &gzip(&[b'a'; 1000]));
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        let mut client = connect_with(Arc::new(handler), config());
+        post(&mut client, "br", b"abc");
+        let head = read_response(&mut BufReader::new(client));
+        assert!(head.starts_with("HTTP/1.1 415 Unsupported Media Type\r\n"));
+        assert!(head.contains("accept-encoding: gzip, deflate\r\n"));
+
+        let mut client = connect_with(Arc::new(handler), config());
+        post(&mut client, "gzip", b"not gzip");
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn expect_continue() {
+        let echo = |request: HttpRequest| {
+            HttpResponse::new(HttpVersion::default(), HttpStatusCode::OK, HttpHeaders::new(), request.body)
+        };
+        let mut client = connect_with(Arc::new(echo), config());
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        // the body is only sent once the server asks for it
+        client.write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "HTTP/1.1 100 Continue\r\n");
+        reader.read_line(&mut line).unwrap();
+        client.write_all(b"hello").unwrap();
+        assert!(read_response(&mut reader).contains("content-length: 5\r\n"));
+
+        // but never to an HTTP/1.0 client
+        client.write_all(b"POST / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.write_all(b"hello").unwrap();
+        assert!(read_response(&mut reader).starts_with("HTTP/1.0 200 OK\r\n"));
+    }
+
+    #[test]
+    fn expect_continue_refused() {
+        let config = || ConnectionConfig {
+            limits: ParseLimits { max_body_size: 16, ..ParseLimits::default() },
+            ..config()
+        };
+
+        let mut client = connect(config());
+        client.write_all(b"POST / HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 17\r\n\r\n").unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 413 Content Too Large\r\n"));
+
+        let mut client = connect(config());
+        client.write_all(b"POST / HTTP/1.1\r\nExpect: the-unexpected\r\nContent-Length: 5\r\n\r\nhello").unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+
+        let continue_handler = |request: &HttpRequest| match request.uri.path() {
+            "/uploads" => None,
+            _ => Some(HttpResponse::new(HttpVersion::default(), HttpStatusCode::EXPECTATION_FAILED, HttpHeaders::new(), None))
+        };
+        let mut client = connect(ConnectionConfig { continue_handler: Some(Arc::new(continue_handler)), ..config() });
+        client.write_all(b"PUT /elsewhere HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n").unwrap();
+        let head = read_response(&mut BufReader::new(client));
+        assert!(head.starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+        assert!(head.contains("connection: close\r\n"));
+
+        // an unknown expectation is refused before the continue_handler sees the request
+        let consulted = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let continue_handler = {
+            let consulted = consulted.clone();
+            move |_request: &HttpRequest| {
+                consulted.store(true, std::sync::atomic::Ordering::SeqCst);
+                None
+            }
+        };
+        let mut client = connect(ConnectionConfig { continue_handler: Some(Arc::new(continue_handler)), ..config() });
+        client.write_all(b"PUT /uploads HTTP/1.1\r\nExpect: 100-continue, foo\r\nContent-Length: 5\r\n\r\n").unwrap();
+        assert!(read_response(&mut BufReader::new(client)).starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+        assert!(!consulted.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[test]
+    fn head_has_no_body() {
+        let mut client = connect(config());
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        // the same content-length as a GET, but no body follows it
+        client.write_all(b"HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n").unwrap();
+        let mut head = String::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" { break; }
+            head.push_str(&line);
+        }
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("content-length: 22\r\n"));
+        assert!(read_response(&mut reader).starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn head_of_chunked_body() {
+        let handler = |_req: HttpRequest| {
+            let body = HttpBody::from_chunks(vec![b"streamed".to_vec()]);
+            HttpResponse::new(HttpVersion::default(), HttpStatusCode::OK, HttpHeaders::new(), body)
+        };
+        let mut client = connect_with(Arc::new(handler), config());
+        client.write_all(b"HEAD / HTTP/1.1\r\nConnection: close\r\n\r\n").unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(!response.contains("transfer-encoding"));
+        assert!(response.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn not_modified_has_no_content_type() {
+        let handler = |req: HttpRequest| {
+            crate::test_util::ok(&[("content-type", "text/plain"), ("etag", "\"v1\"")], Some(b"hello".to_vec()))
+                .evaluate_preconditions(&req)
+        };
+        let mut client = connect_with(Arc::new(handler), config());
+        client.write_all(b"GET / HTTP/1.1\r\nIf-None-Match: \"v1\"\r\nConnection: close\r\n\r\n").unwrap();
+
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(response.contains("etag: \"v1\"\r\n"));
+        assert!(!response.contains("content-type"));
+    }
+}
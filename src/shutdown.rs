@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Stops an HttpServer which is listening, possibly on another thread. Once shut down the
+/// server stops accepting connections, closes idle ones, lets requests already being handled
+/// finish until its `shutdown_timeout`, and then `HttpServer::listen` returns.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<ShutdownState>,
+}
+
+#[derive(Default)]
+struct ShutdownState {
+    shutting_down: AtomicBool,
+    /// Where the server is listening, so that a blocked accept can be woken up.
+    listening: Mutex<Vec<SocketAddr>>,
+    next_id: AtomicUsize,
+    connections: Mutex<HashMap<usize, Connection>>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Whether the connection is waiting for a request, rather than handling one.
+    idle: bool,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts shutting the server down. Returns straight away, `HttpServer::listen` returns
+    /// once the server has drained.
+    pub fn shutdown(&self) {
+        if self.state.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        if let Ok(connections) = self.state.connections.lock() {
+            for connection in connections.values().filter(|c| c.idle) {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+
+        // accept blocks until a connection arrives, so make one
+        let listening = self.state.listening.lock().map(|l| l.clone()).unwrap_or_default();
+        for mut addr in listening {
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.state.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Records that the server is accepting connections on addr.
+    pub(crate) fn listening_on(&self, addr: SocketAddr) {
+        if let Ok(mut listening) = self.state.listening.lock() {
+            listening.push(addr);
+        }
+    }
+
+    /// Starts tracking a connection, so that it can be closed on shutdown. The connection
+    /// starts out idle, and stops being tracked when the returned guard is dropped.
+    pub(crate) fn track(&self, stream: &TcpStream) -> std::io::Result<ConnectionGuard> {
+        let id = self.state.next_id.fetch_add(1, Ordering::SeqCst);
+        let stream = stream.try_clone()?;
+        if let Ok(mut connections) = self.state.connections.lock() {
+            connections.insert(id, Connection { stream, idle: true });
+        }
+        Ok(ConnectionGuard { handle: self.clone(), id })
+    }
+
+    /// Closes every tracked connection, busy or not.
+    pub(crate) fn close_connections(&self) {
+        if let Ok(connections) = self.state.connections.lock() {
+            for connection in connections.values() {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Whether any tracked connection is still open.
+    pub(crate) fn has_connections(&self) -> bool {
+        self.state.connections.lock().map(|c| !c.is_empty()).unwrap_or(false)
+    }
+}
+
+/// Keeps a connection tracked by a ShutdownHandle while it is alive.
+pub(crate) struct ConnectionGuard {
+    handle: ShutdownHandle,
+    id: usize,
+}
+
+impl ConnectionGuard {
+    /// Marks the connection idle or busy. Idle connections are closed as soon as the server
+    /// starts shutting down.
+    pub(crate) fn set_idle(&self, idle: bool) {
+        if let Ok(mut connections) = self.handle.state.connections.lock() {
+            if let Some(connection) = connections.get_mut(&self.id) {
+                connection.idle = idle;
+            }
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.handle.state.connections.lock() {
+            connections.remove(&self.id);
+        }
+    }
+}
@@ -1,42 +1,188 @@
-use std::io::{Read, Write, BufReader};
+use std::io::{Read, Write, BufRead, BufReader, BufWriter};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
 
-use crate::request::{HttpRequest};
-use crate::parser::HttpParser;
+use crate::request::{HttpRequest, HttpResponse, HttpVersion, HttpStatusCode, HttpHeaders, HttpBody};
+use crate::parser::{HttpParser, ParseError, ParseLimits};
+
+impl HttpRequest {
+    /// Fails with `ParseError::ExpectationFailed` when the `Expect` header holds anything
+    /// other than `100-continue`, which is the only expectation there is. Checked before
+    /// anything else is done with the request, so that it is refused with a 417 first.
+    pub fn check_expectations(&self) -> Result<(), ParseError> {
+        match self.headers.get_combined("expect") {
+            Some(expect) if expect.split(',').any(|e| !e.trim().eq_ignore_ascii_case("100-continue")) => {
+                Err(ParseError::ExpectationFailed(expect))
+            },
+            _ => Ok(())
+        }
+    }
+}
+
+/// A stream whose blocking reads and writes can be made to time out, like TcpStream.
+pub trait TimeoutStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl TimeoutStream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+impl<T: TimeoutStream + ?Sized> TimeoutStream for &mut T {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        (**self).set_write_timeout(timeout)
+    }
+}
+
+/// private: Reads from a stream until a deadline, after which reads fail with `TimedOut`.
+/// Each read may only block for as long as is left before the deadline.
+struct DeadlineReader<T> {
+    inner: T,
+    deadline: Option<Instant>,
+}
+
+impl<T: Read + TimeoutStream> Read for DeadlineReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        self.inner.set_read_timeout(remaining)?;
+
+        // a timed out socket read reports WouldBlock on some platforms
+        self.inner.read(buf).map_err(|err| match err.kind() {
+            std::io::ErrorKind::WouldBlock => std::io::ErrorKind::TimedOut.into(),
+            _ => err
+        })
+    }
+}
 
 /// Wraps std::net::TcpStream with functionality to read/write structured http requests/responses.
-pub struct HttpStream<T: Read + Write + Unpin> {
-    stream: T,
-    has_been_read: bool
+/// The stream is read through a single buffer which lives as long as the HttpStream, so bytes
+/// of pipelined requests read ahead of the current request are kept for the next read.
+pub struct HttpStream<T: Read + Write + TimeoutStream + Unpin> {
+    stream: BufReader<DeadlineReader<T>>,
+    /// The limits each request read from the stream is parsed with.
+    pub limits: ParseLimits,
+    /// How long to wait for the first byte of a request. `None` waits forever.
+    pub idle_timeout: Option<Duration>,
+    /// How long the client has to send the head of a request, once it has started it.
+    pub header_timeout: Option<Duration>,
+    /// How long the client has to send the body of a request, once its head is read.
+    pub body_timeout: Option<Duration>,
+    /// How long a single write may block.
+    pub write_timeout: Option<Duration>,
 }
 
-impl<T: Read + Write + Unpin> HttpStream<T> {
+impl<T: Read + Write + TimeoutStream + Unpin> HttpStream<T> {
     pub fn new(stream: T) -> Self {
         HttpStream {
-            stream,
-            has_been_read: false
+            stream: BufReader::new(DeadlineReader { inner: stream, deadline: None }),
+            limits: ParseLimits::default(),
+            idle_timeout: None,
+            header_timeout: None,
+            body_timeout: None,
+            write_timeout: None,
         }
     }
 
-    pub fn read_http(&mut self) -> Result<HttpRequest, ()> {
-        if self.has_been_read {
-            Err(())
-        } else {
-            self.has_been_read = true;
-            Ok(HttpParser::new(BufReader::new(&mut self.stream)).parse_http_request())
+    /// Reads and parses the next request from the stream. Fails with `ParseError::Eof`
+    /// once the peer has closed the connection, with an `Io` error of kind `TimedOut` if no
+    /// request starts within the idle_timeout, and with `ParseError::Timeout` if a request
+    /// isn't finished within the header_timeout and body_timeout.
+    pub fn read_http(&mut self) -> Result<HttpRequest, ParseError> {
+        let mut request = self.read_head()?;
+        request.check_expectations()?;
+        self.read_body(&mut request)?;
+        Ok(request)
+    }
+
+    /// Reads the head of the next request, leaving its body to be read by `read_body`, so
+    /// that the request can be refused before a client waiting on `100 Continue` sends it.
+    pub fn read_head(&mut self) -> Result<HttpRequest, ParseError> {
+        // wait for the request to start, unless it has already been read
+        self.set_deadline(self.idle_timeout);
+        if self.stream.fill_buf()?.is_empty() {
+            return Err(ParseError::Eof);
         }
+
+        self.set_deadline(self.header_timeout);
+        let request = HttpParser::with_limits(&mut self.stream, self.limits).parse_http_head()
+            .map_err(Self::timeout_error);
+        self.set_deadline(None);
+        request
     }
 
-    pub fn write(&mut self, data: &[u8]) {
-        self.stream.write(data).unwrap();
+    /// Reads the body of a request whose head was read by `read_head`, and whose
+    /// expectations were checked by `HttpRequest::check_expectations`. A client waiting on
+    /// `100 Continue` is sent one first, unless the body is refused for being larger than the
+    /// limits allow.
+    pub fn read_body(&mut self, request: &mut HttpRequest) -> Result<(), ParseError> {
+        // a client which has started sending the body anyway needn't be told to
+        let has_body = request.is_chunked()
+            || request.headers.get("content-length").map(|l| l.trim() != "0").unwrap_or(false);
+        if request.expects_continue() && has_body && self.stream.buffer().is_empty() {
+            let declared = request.headers.get("content-length").and_then(|l| l.trim().parse::<u64>().ok());
+            if declared.map(|length| length > self.limits.max_body_size as u64).unwrap_or(false) {
+                return Err(ParseError::BodyTooLarge);
+            }
+            let response = HttpResponse::new(HttpVersion::Http11, HttpStatusCode::CONTINUE, HttpHeaders::new(), HttpBody::Empty);
+            self.write_response(response)?;
+        }
+
+        self.set_deadline(self.body_timeout);
+        let body = HttpParser::with_limits(&mut self.stream, self.limits).parse_http_body(request)
+            .map_err(Self::timeout_error);
+        self.set_deadline(None);
+        body
+    }
+
+    /// private: Starts a deadline for the reads which follow, timeout from now.
+    fn set_deadline(&mut self, timeout: Option<Duration>) {
+        self.stream.get_mut().deadline = timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// private: Reports reads which ran past their deadline as a `ParseError::Timeout`.
+    fn timeout_error(err: ParseError) -> ParseError {
+        match err {
+            ParseError::Io(err) if err.kind() == std::io::ErrorKind::TimedOut => ParseError::Timeout,
+            err => err
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let stream = &mut self.stream.get_mut().inner;
+        stream.set_write_timeout(self.write_timeout)?;
+        stream.write_all(data)
+    }
+
+    /// Writes a response to the stream, streaming its body if it has one.
+    pub fn write_response(&mut self, response: HttpResponse) -> std::io::Result<()> {
+        let stream = &mut self.stream.get_mut().inner;
+        stream.set_write_timeout(self.write_timeout)?;
+        let mut writer = BufWriter::new(stream);
+        response.write_to(&mut writer)?;
+        writer.flush()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::request::{HttpMethod};
+    use crate::request::{HttpMethod, HttpVersion, HttpHeaders};
     use std::collections::HashMap;
-    use std::path::PathBuf;
+    use std::convert::TryInto;
 
     use std::cmp::min;
 
@@ -52,6 +198,7 @@ mod tests {
         ) -> std::io::Result<usize> {
             let size: usize = min(self.read_data.len(), buf.len());
             buf[..size].copy_from_slice(&self.read_data[..size]);
+            self.read_data.drain(..size);
             Ok(size)
         }
     }
@@ -61,7 +208,7 @@ mod tests {
             &mut self,
             buf: &[u8],
         ) -> std::io::Result<usize> {
-            self.write_data = Vec::from(buf);
+            self.write_data.extend_from_slice(buf);
             Ok(buf.len())
         }
         fn flush(&mut self) -> std::io::Result<()> {
@@ -69,33 +216,46 @@ mod tests {
         }
     }
 
+    impl TimeoutStream for MockTcpStream {
+        fn set_read_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+        fn set_write_timeout(&self, _timeout: Option<Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
     use std::marker::Unpin;
     impl Unpin for MockTcpStream {}
 
     const SIMPLE_REQUEST_STR: &str = "GET / HTTP/1.1\r\nAccept: */*\r\n\r\n";
     fn get_simple_request() -> HttpRequest {
-        let mut ex_headers = HashMap::new();
-        ex_headers.insert("accept".to_string(), "*/*".to_string());
+        let mut ex_headers = HttpHeaders::new();
+        ex_headers.insert("Accept", "*/*");
         HttpRequest {
             method: HttpMethod::GET,
-            uri: PathBuf::from("/"),
-            http_version: "HTTP/1.1".to_string(),
+            uri: "/".try_into().unwrap(),
+            http_version: HttpVersion::Http11,
             headers: ex_headers,
-            body: None
+            body: None,
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
         }
     }
 
     const BODIED_REQUEST_STR: &str = "POST / HTTP/1.1\r\nAccept: */*\r\nContent-Length: 14\r\n\r\nThis is a body\r\n\r\n";
     fn get_bodied_request() -> HttpRequest {
-        let mut ex_headers = HashMap::new();
-        ex_headers.insert("accept".to_string(), "*/*".to_string());
-        ex_headers.insert("content-length".to_string(), "14".to_string());
+        let mut ex_headers = HttpHeaders::new();
+        ex_headers.insert("Accept", "*/*");
+        ex_headers.insert("Content-Length", "14");
         HttpRequest {
             method: HttpMethod::POST,
-            uri: PathBuf::from("/"),
-            http_version: "HTTP/1.1".to_string(),
+            uri: "/".try_into().unwrap(),
+            http_version: HttpVersion::Http11,
             headers: ex_headers,
-            body: Some(Vec::from("This is a body".as_bytes()))
+            body: Some(Vec::from("This is a body".as_bytes())),
+            trailers: HttpHeaders::new(),
+            params: HashMap::new()
         }
     }
 
@@ -112,7 +272,7 @@ mod tests {
         let mut http_stream = HttpStream::new(&mut mock_stream);
 
         let request = http_stream.read_http().unwrap();
-        http_stream.write("response".as_bytes());
+        http_stream.write("response".as_bytes()).unwrap();
 
         assert_eq!(request, expected_request);
         assert!(mock_stream.write_data.starts_with("response".as_bytes()));
@@ -123,4 +283,18 @@ mod tests {
         stream_request_helper(SIMPLE_REQUEST_STR, get_simple_request());
         stream_request_helper(BODIED_REQUEST_STR, get_bodied_request());
     }
+
+    #[test]
+    fn stream_pipelined() {
+        let mut mock_stream = MockTcpStream {
+            read_data: Vec::from(format!("{}{}{}", BODIED_REQUEST_STR.trim_end(), SIMPLE_REQUEST_STR, SIMPLE_REQUEST_STR)),
+            write_data: Vec::new()
+        };
+        let mut http_stream = HttpStream::new(&mut mock_stream);
+
+        assert_eq!(http_stream.read_http().unwrap(), get_bodied_request());
+        assert_eq!(http_stream.read_http().unwrap(), get_simple_request());
+        assert_eq!(http_stream.read_http().unwrap(), get_simple_request());
+        assert!(matches!(http_stream.read_http(), Err(ParseError::Eof)));
+    }
 }
@@ -0,0 +1,23 @@
+//! Fixtures shared by the unit tests of each module.
+
+use crate::parser::HttpParser;
+use crate::request::{HttpRequest, HttpResponse, HttpVersion, HttpStatusCode, HttpHeaders, HttpBody};
+
+/// Parses request_str, which must be a complete, valid request.
+pub fn request(request_str: &str) -> HttpRequest {
+    HttpParser::new(request_str.as_bytes()).parse_http_request().unwrap()
+}
+
+/// A 200 with the given headers and body.
+pub fn ok(headers: &[(&str, &str)], body: impl Into<HttpBody>) -> HttpResponse {
+    let mut response_headers = HttpHeaders::new();
+    for (key, value) in headers {
+        response_headers.append(key, value);
+    }
+    HttpResponse::new(HttpVersion::default(), HttpStatusCode::OK, response_headers, body)
+}
+
+/// The body of response as it is written, everything after the head.
+pub fn body(response: HttpResponse) -> String {
+    String::from(response).split_once("\r\n\r\n").unwrap().1.to_string()
+}
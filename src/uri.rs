@@ -0,0 +1,261 @@
+use std::fmt;
+
+pub use target::*;
+pub use query::*;
+
+/// Decodes `%XX` escapes in s. Malformed escapes are kept as they are, and any bytes which
+/// don't form valid UTF-8 are replaced.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+mod target {
+    use super::*;
+
+    /// The request-target of a Request-Line, in one of the four forms defined at
+    /// <https://www.rfc-editor.org/rfc/rfc9112#name-request-target>.
+    /// Components are kept as they were sent, still percent-encoded.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum RequestTarget {
+        /// `/path?query`, used by most requests.
+        Origin { path: String, query: Option<String> },
+        /// `scheme://authority/path?query`, used for requests to proxies.
+        Absolute { scheme: String, authority: String, path: String, query: Option<String> },
+        /// `host:port`, only used by CONNECT.
+        Authority(String),
+        /// `*`, only used by a server-wide OPTIONS.
+        Asterisk,
+    }
+
+    impl RequestTarget {
+        /// The path, still percent-encoded. This is `*` for the asterisk-form, and empty
+        /// for the authority-form.
+        pub fn path(&self) -> &str {
+            match self {
+                RequestTarget::Origin { path, .. } | RequestTarget::Absolute { path, .. } => path,
+                RequestTarget::Authority(_) => "",
+                RequestTarget::Asterisk => "*",
+            }
+        }
+
+        /// The query string, without the leading `?`, still percent-encoded.
+        pub fn query(&self) -> Option<&str> {
+            match self {
+                RequestTarget::Origin { query, .. } | RequestTarget::Absolute { query, .. } => query.as_deref(),
+                _ => None,
+            }
+        }
+
+        /// The path with percent-encoding decoded.
+        pub fn decoded_path(&self) -> String {
+            percent_decode(self.path())
+        }
+
+        /// The non-empty segments of the path, each percent-decoded. Decoding each segment
+        /// separately keeps an encoded `%2F` from splitting a segment in two.
+        pub fn segments(&self) -> Vec<String> {
+            self.path()
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .map(percent_decode)
+                .collect()
+        }
+
+        /// The parsed query parameters. Empty if there is no query.
+        pub fn query_params(&self) -> QueryParams {
+            QueryParams::parse(self.query().unwrap_or(""))
+        }
+
+        /// private: Split `path?query`, dropping any `#fragment` a client sent by mistake.
+        fn split_path(s: &str) -> (String, Option<String>) {
+            let s = s.split('#').next().unwrap_or("");
+            match s.split_once('?') {
+                Some((path, query)) => (path.to_string(), Some(query.to_string())),
+                None => (s.to_string(), None)
+            }
+        }
+
+        /// private: Whether s is a plausible `host[:port]`, without any path or whitespace.
+        fn is_authority(s: &str) -> bool {
+            !s.is_empty() && !s.chars().any(|c| c.is_whitespace() || c.is_control() || "/?#".contains(c))
+        }
+    }
+
+    impl std::convert::TryFrom<&str> for RequestTarget {
+        type Error = String;
+
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            let invalid = || format!("Invalid RequestTarget: {}", value);
+            if value.is_empty() || value.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                return Err(invalid());
+            }
+
+            if value == "*" {
+                return Ok(RequestTarget::Asterisk);
+            }
+
+            if value.starts_with('/') {
+                let (path, query) = Self::split_path(value);
+                return Ok(RequestTarget::Origin { path, query });
+            }
+
+            if let Some((scheme, rest)) = value.split_once("://") {
+                let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+                let end = rest.find(|c| "/?#".contains(c)).unwrap_or(rest.len());
+                let (authority, rest) = rest.split_at(end);
+                if !valid_scheme || !Self::is_authority(authority) {
+                    return Err(invalid());
+                }
+
+                let (mut path, query) = Self::split_path(rest);
+                if path.is_empty() {
+                    path = "/".to_string();
+                }
+                return Ok(RequestTarget::Absolute {
+                    scheme: scheme.to_ascii_lowercase(),
+                    authority: authority.to_string(),
+                    path,
+                    query
+                });
+            }
+
+            if Self::is_authority(value) {
+                return Ok(RequestTarget::Authority(value.to_string()));
+            }
+
+            Err(invalid())
+        }
+    }
+
+    impl fmt::Display for RequestTarget {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                RequestTarget::Origin { path, .. } => write!(f, "{}", path)?,
+                RequestTarget::Absolute { scheme, authority, path, .. } => {
+                    write!(f, "{}://{}{}", scheme, authority, path)?
+                },
+                RequestTarget::Authority(authority) => return write!(f, "{}", authority),
+                RequestTarget::Asterisk => return write!(f, "*"),
+            }
+            match self.query() {
+                Some(query) => write!(f, "?{}", query),
+                None => Ok(())
+            }
+        }
+    }
+}
+
+mod query {
+    use super::*;
+
+    /// Query parameters, in the order they were sent. A name may appear more than once.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct QueryParams(pub Vec<(String, String)>);
+
+    impl QueryParams {
+        /// Parses an `application/x-www-form-urlencoded` query string. `+` is decoded as a space,
+        /// and a name without `=` gets an empty value.
+        pub fn parse(query: &str) -> Self {
+            let decode = |s: &str| percent_decode(&s.replace('+', " "));
+            QueryParams(query
+                .split('&')
+                .filter(|pair| !pair.is_empty())
+                .map(|pair| match pair.split_once('=') {
+                    Some((name, value)) => (decode(name), decode(value)),
+                    None => (decode(pair), String::new())
+                })
+                .collect())
+        }
+
+        /// The first value of name.
+        pub fn get(&self, name: &str) -> Option<&str> {
+            self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+        }
+
+        /// Every value of name, in order.
+        pub fn get_all(&self, name: &str) -> Vec<&str> {
+            self.0.iter().filter(|(k, _)| k == name).map(|(_, v)| v.as_str()).collect()
+        }
+
+        pub fn contains_key(&self, name: &str) -> bool {
+            self.0.iter().any(|(k, _)| k == name)
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        pub fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("/a%20b"), "/a b");
+        assert_eq!(percent_decode("%E2%9C%93"), "✓");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn origin_form() {
+        let target = RequestTarget::try_from("/users/j%2Fd/files?sort=name&tag=a&tag=b+c#top").unwrap();
+        assert_eq!(target.path(), "/users/j%2Fd/files");
+        assert_eq!(target.query(), Some("sort=name&tag=a&tag=b+c"));
+        assert_eq!(target.segments(), vec!["users", "j/d", "files"]);
+        assert_eq!(target.decoded_path(), "/users/j/d/files");
+
+        let params = target.query_params();
+        assert_eq!(params.get("sort"), Some("name"));
+        assert_eq!(params.get_all("tag"), vec!["a", "b c"]);
+        assert_eq!(params.get("missing"), None);
+
+        assert_eq!(target.to_string(), "/users/j%2Fd/files?sort=name&tag=a&tag=b+c");
+    }
+
+    #[test]
+    fn other_forms() {
+        let target = RequestTarget::try_from("HTTP://example.com:8080?q").unwrap();
+        assert_eq!(target, RequestTarget::Absolute {
+            scheme: "http".to_string(),
+            authority: "example.com:8080".to_string(),
+            path: "/".to_string(),
+            query: Some("q".to_string())
+        });
+
+        assert_eq!(RequestTarget::try_from("example.com:443"), Ok(RequestTarget::Authority("example.com:443".to_string())));
+        assert_eq!(RequestTarget::try_from("*"), Ok(RequestTarget::Asterisk));
+
+        assert!(RequestTarget::try_from("").is_err());
+        assert!(RequestTarget::try_from("/a b").is_err());
+        assert!(RequestTarget::try_from("1http://host/").is_err());
+        assert!(RequestTarget::try_from("http:///path").is_err());
+    }
+}